@@ -0,0 +1,86 @@
+use std::sync::Arc;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use glam::Vec3;
+use ik3::{
+    Ccd, DampedLeastSquares, Fabrik, FabrikChain, KinematicsMode, MotionHeuristics,
+    PoseDiscrepancy, Solver, Target,
+};
+
+const SIZES: [usize; 4] = [3, 10, 100, 1000];
+const ITERATIONS: usize = 10;
+
+/// A straight chain of `joints` joints, one unit long in total, standing up along Y.
+fn chain(joints: usize, lock_ground: bool, solver: Arc<dyn Solver>) -> FabrikChain {
+    let step = 1.0 / (joints - 1) as f32;
+    let joints = (0..joints)
+        .map(|i| Vec3::new(0.0, i as f32 * step, 0.0))
+        .collect();
+    let mut chain = FabrikChain::new(joints, MotionHeuristics::default()).unwrap();
+    chain.lock_ground = lock_ground;
+    chain.solver = solver;
+    // Never count as reached, so every solve runs all of its iterations.
+    chain.tolerance = 0.0;
+    chain
+}
+
+fn targets(chain: &FabrikChain, multiple: bool) -> Vec<Target> {
+    let tip = chain.joints.len() - 1;
+    let mut targets = vec![Target::new(tip, Vec3::new(0.5, 0.5, 0.0))];
+    if multiple {
+        targets.push(Target::new(tip / 2, Vec3::new(0.3, 0.2, 0.1)));
+    }
+    targets
+}
+
+fn bench_solver(c: &mut Criterion, name: &str, solver: Arc<dyn Solver>) {
+    let mut group = c.benchmark_group(name);
+    for size in SIZES {
+        for lock_ground in [true, false] {
+            for multiple in [false, true] {
+                let id = format!(
+                    "{}_{}",
+                    if multiple {
+                        "two_targets"
+                    } else {
+                        "one_target"
+                    },
+                    if lock_ground { "locked" } else { "free" },
+                );
+                let mut chain = chain(size, lock_ground, solver.clone());
+                chain.targets = targets(&chain, multiple);
+                group.bench_with_input(BenchmarkId::new(id, size), &chain, |b, chain| {
+                    b.iter_batched_ref(
+                        || chain.clone(),
+                        |chain| {
+                            chain
+                                .solve(
+                                    ITERATIONS,
+                                    PoseDiscrepancy::WithinTolerance,
+                                    &mut KinematicsMode::default(),
+                                )
+                                .unwrap()
+                        },
+                        criterion::BatchSize::SmallInput,
+                    )
+                });
+            }
+        }
+    }
+    group.finish();
+}
+
+fn fabrik(c: &mut Criterion) {
+    bench_solver(c, "fabrik", Arc::new(Fabrik));
+}
+
+fn ccd(c: &mut Criterion) {
+    bench_solver(c, "ccd", Arc::new(Ccd));
+}
+
+fn damped_least_squares(c: &mut Criterion) {
+    bench_solver(c, "dls", Arc::new(DampedLeastSquares::default()));
+}
+
+criterion_group!(benches, fabrik, ccd, damped_least_squares);
+criterion_main!(benches);
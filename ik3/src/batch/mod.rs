@@ -0,0 +1,307 @@
+use crate::extern_prelude::*;
+use crate::{
+    FabrikChain, IkError, JointConstraint, KinematicsMode, MotionHeuristics, PoseDiscrepancy,
+    SolveReport, Solver, SolverSettings, Target,
+};
+use core::ops::Range;
+
+/// Per-chain state that is only read once per solve, kept out of the way of the joint data.
+#[derive(Debug, Clone)]
+struct ChainState {
+    /// Settings without a solver kind, since `solver` carries the solver itself.
+    settings: SolverSettings,
+    motion_heuristics: MotionHeuristics,
+    solver: Arc<dyn Solver>,
+    prev_time: Option<Instant>,
+    angles: Vec<f32>,
+    prev_angles: Vec<f32>,
+    angular_velocities: Vec<f32>,
+}
+
+impl ChainState {
+    /// Swaps the state held here with `chain`'s, so it moves in and back out of the scratch
+    /// chain without being copied.
+    fn exchange(&mut self, chain: &mut FabrikChain) {
+        core::mem::swap(&mut self.motion_heuristics, &mut chain.motion_heuristics);
+        core::mem::swap(&mut self.solver, &mut chain.solver);
+        core::mem::swap(&mut self.prev_time, &mut chain.prev_time);
+        core::mem::swap(&mut self.angles, &mut chain.angles);
+        core::mem::swap(&mut self.prev_angles, &mut chain.prev_angles);
+        core::mem::swap(&mut self.angular_velocities, &mut chain.angular_velocities);
+    }
+}
+
+/// Many chains stored structure-of-arrays style: the joints, constraints, segment lengths,
+/// segment transforms and targets of every chain each live in one shared `Vec`, and chains
+/// carry no initial state or fantasy limb of their own.
+///
+/// The layout only holds at rest: to solve a chain, its joints, lengths, constraints and
+/// targets are copied into a scratch `FabrikChain`, which runs exactly what
+/// `FabrikChain::solve` runs with `PoseDiscrepancy::WithinTolerance`, and the solved joints
+/// are copied back. The results therefore match solving each chain on its own. The scratch
+/// chain's buffers are reused from one chain to the next, but the solve itself still
+/// allocates as it would for a standalone chain, e.g. for its `SolveReport`. With the `rayon`
+/// feature, `par_solve` spreads the chains over rayon's thread pool, with a scratch chain per
+/// thread.
+#[derive(Debug, Clone, Default)]
+pub struct ChainBatch {
+    joints: Vec<Vec3>,
+    constraints: Vec<JointConstraint>,
+    lengths: Vec<f32>,
+    #[cfg(feature = "bevy_transform")]
+    segment_transforms: Vec<Transform>,
+    targets: Vec<Target>,
+    joint_ranges: Vec<Range<usize>>,
+    target_ranges: Vec<Range<usize>>,
+    states: Vec<ChainState>,
+}
+
+/// One chain's share of a `ChainBatch`, borrowed for solving.
+struct ChainSlot<'a> {
+    joints: &'a mut [Vec3],
+    #[cfg(feature = "bevy_transform")]
+    segment_transforms: &'a mut [Transform],
+    constraints: &'a [JointConstraint],
+    lengths: &'a [f32],
+    targets: &'a [Target],
+    state: &'a mut ChainState,
+}
+
+impl ChainBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+
+    /// Adds a copy of `chain`'s current pose, targets and settings, returning its index.
+    pub fn push(&mut self, chain: &FabrikChain) -> Result<usize, IkError> {
+        chain.validate()?;
+        let start = self.joints.len();
+        self.joints.extend_from_slice(&chain.joints);
+        self.constraints.extend(
+            (0..chain.joints.len()).map(|i| chain.constraints.get(i).copied().unwrap_or_default()),
+        );
+        self.lengths.extend_from_slice(&chain.lengths);
+        #[cfg(feature = "bevy_transform")]
+        {
+            self.segment_transforms
+                .resize(self.lengths.len(), Transform::IDENTITY);
+            let segments = self.lengths.len() - chain.lengths.len()..self.lengths.len();
+            for (slot, transform) in self.segment_transforms[segments]
+                .iter_mut()
+                .zip(&chain.segment_transforms)
+            {
+                *slot = *transform;
+            }
+        }
+        self.joint_ranges.push(start..self.joints.len());
+        let target_start = self.targets.len();
+        self.targets.extend_from_slice(&chain.targets);
+        self.target_ranges.push(target_start..self.targets.len());
+        self.states.push(ChainState {
+            settings: SolverSettings {
+                solver: None,
+                ..chain.solver_settings()
+            },
+            motion_heuristics: chain.motion_heuristics.clone(),
+            solver: Arc::clone(&chain.solver),
+            prev_time: chain.prev_time,
+            angles: chain.angles.clone(),
+            prev_angles: chain.prev_angles.clone(),
+            angular_velocities: chain.angular_velocities.clone(),
+        });
+        Ok(self.states.len() - 1)
+    }
+
+    fn check(&self, index: usize) -> Result<(), IkError> {
+        if index >= self.len() {
+            return Err(IkError::JointOutOfRange {
+                index,
+                len: self.len(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Segment `i` of a chain sits between its joints `i` and `i + 1`, and each chain before it
+    /// has one segment fewer than joints.
+    fn segment_range(&self, index: usize) -> Range<usize> {
+        let joints = &self.joint_ranges[index];
+        joints.start - index..joints.end - index - 1
+    }
+
+    /// The joints of chain `index`, or `None` if the batch has no such chain.
+    pub fn joints(&self, index: usize) -> Option<&[Vec3]> {
+        Some(&self.joints[self.joint_ranges.get(index)?.clone()])
+    }
+
+    #[cfg(feature = "bevy_transform")]
+    pub fn segment_transforms(&self, index: usize) -> Option<&[Transform]> {
+        (index < self.len()).then(|| &self.segment_transforms[self.segment_range(index)])
+    }
+
+    pub fn angular_velocities(&self, index: usize) -> Option<&[f32]> {
+        Some(&self.states.get(index)?.angular_velocities)
+    }
+
+    pub fn targets(&self, index: usize) -> Option<&[Target]> {
+        Some(&self.targets[self.target_ranges.get(index)?.clone()])
+    }
+
+    /// The targets of chain `index`, to move them between solves.
+    pub fn targets_mut(&mut self, index: usize) -> Option<&mut [Target]> {
+        let range = self.target_ranges.get(index)?.clone();
+        Some(&mut self.targets[range])
+    }
+
+    /// Replaces the targets of chain `index`, which may change how many it has.
+    pub fn set_targets(&mut self, index: usize, targets: &[Target]) -> Result<(), IkError> {
+        self.check(index)?;
+        let joints = self.joint_ranges[index].len();
+        if let Some(target) = targets.iter().find(|target| target.index >= joints) {
+            return Err(IkError::JointOutOfRange {
+                index: target.index,
+                len: joints,
+            });
+        }
+        let range = self.target_ranges[index].clone();
+        let shift = targets.len() as isize - range.len() as isize;
+        self.targets.splice(range.clone(), targets.iter().copied());
+        self.target_ranges[index] = range.start..range.start + targets.len();
+        for later in &mut self.target_ranges[index + 1..] {
+            *later = (later.start as isize + shift) as usize..(later.end as isize + shift) as usize;
+        }
+        Ok(())
+    }
+
+    /// Rebuilds chain `index` as a standalone `FabrikChain`, in its current pose.
+    pub fn chain(&self, index: usize) -> Result<FabrikChain, IkError> {
+        self.check(index)?;
+        let state = &self.states[index];
+        let joints = self.joint_ranges[index].clone();
+        let segments = self.segment_range(index);
+        let mut chain = FabrikChain::new(
+            self.joints[joints.clone()].to_vec(),
+            state.motion_heuristics.clone(),
+        )?;
+        chain.lengths = self.lengths[segments.clone()].to_vec();
+        chain.constraints = self.constraints[joints].to_vec();
+        chain.targets = self.targets[self.target_ranges[index].clone()].to_vec();
+        #[cfg(feature = "bevy_transform")]
+        {
+            chain.segment_transforms = self.segment_transforms[segments].to_vec();
+        }
+        chain.apply_solver_settings(state.settings.clone());
+        chain.solver = Arc::clone(&state.solver);
+        chain.prev_time = state.prev_time;
+        chain.angles = state.angles.clone();
+        chain.prev_angles = state.prev_angles.clone();
+        chain.angular_velocities = state.angular_velocities.clone();
+        chain.snapshot();
+        Ok(chain)
+    }
+
+    /// Borrows every chain's share of the batch at once, so they can be solved independently.
+    fn slots(&mut self) -> Vec<ChainSlot<'_>> {
+        let mut joints = self.joints.as_mut_slice();
+        #[cfg(feature = "bevy_transform")]
+        let mut segment_transforms = self.segment_transforms.as_mut_slice();
+        let mut slots = Vec::with_capacity(self.states.len());
+        for (index, state) in self.states.iter_mut().enumerate() {
+            let joint_range = self.joint_ranges[index].clone();
+            let segment_count = joint_range.len() - 1;
+            let (chain_joints, rest) = core::mem::take(&mut joints).split_at_mut(joint_range.len());
+            joints = rest;
+            #[cfg(feature = "bevy_transform")]
+            let chain_segments = {
+                let (chain_segments, rest) =
+                    core::mem::take(&mut segment_transforms).split_at_mut(segment_count);
+                segment_transforms = rest;
+                chain_segments
+            };
+            let segment_start = joint_range.start - index;
+            slots.push(ChainSlot {
+                joints: chain_joints,
+                #[cfg(feature = "bevy_transform")]
+                segment_transforms: chain_segments,
+                constraints: &self.constraints[joint_range],
+                lengths: &self.lengths[segment_start..segment_start + segment_count],
+                targets: &self.targets[self.target_ranges[index].clone()],
+                state,
+            });
+        }
+        slots
+    }
+
+    /// Solves every chain in turn, as `FabrikChain::solve` would with
+    /// `PoseDiscrepancy::WithinTolerance`.
+    pub fn solve(&mut self, iterations: usize) -> Vec<Result<SolveReport, IkError>> {
+        let mut scratch = scratch();
+        self.slots()
+            .into_iter()
+            .map(|slot| slot.solve(&mut scratch, iterations))
+            .collect()
+    }
+
+    /// Like `solve`, but solves the chains in parallel on rayon's thread pool.
+    #[cfg(feature = "rayon")]
+    pub fn par_solve(&mut self, iterations: usize) -> Vec<Result<SolveReport, IkError>> {
+        use rayon::prelude::*;
+        self.slots()
+            .into_par_iter()
+            .map_init(scratch, |scratch, slot| slot.solve(scratch, iterations))
+            .collect()
+    }
+}
+
+/// A chain to load batch entries into. It never gets an initial state or fantasy limb.
+fn scratch() -> FabrikChain {
+    let mut chain = FabrikChain::new(vec![Vec3::ZERO], MotionHeuristics::default())
+        .expect("a single joint is a valid chain");
+    chain.initial_state = None;
+    chain.fantasy_limb = None;
+    chain
+}
+
+impl ChainSlot<'_> {
+    fn solve(self, scratch: &mut FabrikChain, iterations: usize) -> Result<SolveReport, IkError> {
+        scratch.joints.clear();
+        scratch.joints.extend_from_slice(self.joints);
+        scratch.lengths.clear();
+        scratch.lengths.extend_from_slice(self.lengths);
+        scratch.constraints.clear();
+        scratch.constraints.extend_from_slice(self.constraints);
+        scratch.targets.clear();
+        scratch.targets.extend_from_slice(self.targets);
+        scratch.apply_solver_settings(core::mem::take(&mut self.state.settings));
+        self.state.exchange(scratch);
+
+        let report = scratch.solve(
+            iterations,
+            PoseDiscrepancy::WithinTolerance,
+            &mut KinematicsMode::default(),
+        );
+
+        self.joints.copy_from_slice(&scratch.joints);
+        // A solve that failed its checks never got as far as placing the segments.
+        #[cfg(feature = "bevy_transform")]
+        if report.is_ok() {
+            self.segment_transforms
+                .copy_from_slice(&scratch.segment_transforms);
+        }
+        // Procedural parenting may have moved the root and ground.
+        self.state.settings = SolverSettings {
+            solver: None,
+            ..scratch.solver_settings()
+        };
+        self.state.exchange(scratch);
+        report
+    }
+}
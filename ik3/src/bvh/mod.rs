@@ -0,0 +1,379 @@
+//! Reading and writing BVH motion capture.
+//!
+//! Clips are read into a `Bvh`, whose frames can be turned into `targets` for a chain by
+//! mapping BVH joint names to chain indices. Going the other way, `Bvh::from_chain` writes a
+//! hierarchy matching a chain, and `Bvh::push_pose` appends the chain's current pose to it as
+//! a frame, so solved motion can be played back in other tools.
+
+use crate::extern_prelude::*;
+use crate::{FabrikChain, IkError, Target};
+use glam::EulerRot;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BvhChannel {
+    Xposition,
+    Yposition,
+    Zposition,
+    Xrotation,
+    Yrotation,
+    Zrotation,
+}
+
+impl BvhChannel {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "Xposition" => BvhChannel::Xposition,
+            "Yposition" => BvhChannel::Yposition,
+            "Zposition" => BvhChannel::Zposition,
+            "Xrotation" => BvhChannel::Xrotation,
+            "Yrotation" => BvhChannel::Yrotation,
+            "Zrotation" => BvhChannel::Zrotation,
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Display for BvhChannel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BvhJoint {
+    pub name: String,
+    /// Index of the parent joint, `None` for the root.
+    pub parent: Option<usize>,
+    /// Position relative to the parent joint when no channels are applied.
+    pub offset: Vec3,
+    pub channels: Vec<BvhChannel>,
+    /// Offset of the `End Site` below this joint, if it has one.
+    pub end_site: Option<Vec3>,
+}
+
+/// A BVH clip: a hierarchy of joints and one value per channel per frame. Rotations are kept
+/// in degrees and positions in the clip's own units, as in the file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bvh {
+    pub joints: Vec<BvhJoint>,
+    pub frame_time: Duration,
+    pub frames: Vec<Vec<f32>>,
+}
+
+impl Bvh {
+    pub fn parse(source: &str) -> Result<Self, IkError> {
+        let mut tokens = source.split_whitespace();
+        keywords(&mut tokens, &["HIERARCHY"])?;
+
+        let mut joints: Vec<BvhJoint> = Vec::new();
+        // Joints whose braces are still open, with `None` standing for an end site.
+        let mut open: Vec<Option<usize>> = Vec::new();
+        loop {
+            let token = tokens
+                .next()
+                .ok_or_else(|| IkError::Bvh("unexpected end of hierarchy".into()))?;
+            match token {
+                "ROOT" | "JOINT" => {
+                    if (token == "ROOT") != open.is_empty() {
+                        return Err(IkError::Bvh(format!("unexpected {token}")));
+                    }
+                    let name = tokens
+                        .next()
+                        .ok_or_else(|| IkError::Bvh("joint has no name".into()))?;
+                    open_brace(tokens.next())?;
+                    joints.push(BvhJoint {
+                        name: name.to_owned(),
+                        parent: open.last().copied().flatten(),
+                        offset: Vec3::ZERO,
+                        channels: Vec::new(),
+                        end_site: None,
+                    });
+                    open.push(Some(joints.len() - 1));
+                }
+                "End" => {
+                    let parent = open.last().copied().flatten();
+                    if tokens.next() != Some("Site") || parent.is_none() {
+                        return Err(IkError::Bvh("misplaced End Site".into()));
+                    }
+                    open_brace(tokens.next())?;
+                    open.push(None);
+                }
+                "OFFSET" => {
+                    let offset = Vec3::new(
+                        number(tokens.next())?,
+                        number(tokens.next())?,
+                        number(tokens.next())?,
+                    );
+                    match open.last() {
+                        Some(Some(joint)) => joints[*joint].offset = offset,
+                        Some(None) => {
+                            let parent = open[open.len() - 2].unwrap();
+                            joints[parent].end_site = Some(offset);
+                        }
+                        None => return Err(IkError::Bvh("OFFSET outside a joint".into())),
+                    }
+                }
+                "CHANNELS" => {
+                    let Some(Some(joint)) = open.last() else {
+                        return Err(IkError::Bvh("CHANNELS outside a joint".into()));
+                    };
+                    let count = number(tokens.next())? as usize;
+                    for _ in 0..count {
+                        let name = tokens.next().unwrap_or_default();
+                        let channel = BvhChannel::parse(name)
+                            .ok_or_else(|| IkError::Bvh(format!("unknown channel {name:?}")))?;
+                        joints[*joint].channels.push(channel);
+                    }
+                }
+                "}" => {
+                    open.pop()
+                        .ok_or_else(|| IkError::Bvh("unbalanced braces".into()))?;
+                }
+                "MOTION" if open.is_empty() && !joints.is_empty() => break,
+                other => return Err(IkError::Bvh(format!("unexpected {other:?}"))),
+            }
+        }
+
+        keywords(&mut tokens, &["Frames:"])?;
+        let frame_count = number(tokens.next())? as usize;
+        keywords(&mut tokens, &["Frame", "Time:"])?;
+        let frame_time = tokens
+            .next()
+            .and_then(|token| token.parse().ok())
+            .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+            .ok_or_else(|| IkError::Bvh("invalid Frame Time".into()))?;
+        let channel_count = joints.iter().map(|joint| joint.channels.len()).sum();
+        let frames = (0..frame_count)
+            .map(|_| (0..channel_count).map(|_| number(tokens.next())).collect())
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            joints,
+            frame_time,
+            frames,
+        })
+    }
+
+    /// Index of the joint called `name`.
+    pub fn joint(&self, name: &str) -> Option<usize> {
+        self.joints.iter().position(|joint| joint.name == name)
+    }
+
+    /// Transform of every joint relative to its parent in `frame`.
+    pub fn local_transforms(&self, frame: usize) -> Result<Vec<Transform>, IkError> {
+        let values = self.frames.get(frame).ok_or_else(|| {
+            IkError::Bvh(format!(
+                "frame {frame} out of range for {} frames",
+                self.frames.len()
+            ))
+        })?;
+        let mut values = values.iter();
+        Ok(self
+            .joints
+            .iter()
+            .map(|joint| {
+                let mut local = Transform::from_translation(joint.offset);
+                for channel in &joint.channels {
+                    let value = values.next().copied().unwrap_or_default();
+                    let angle = value.to_radians();
+                    match channel {
+                        BvhChannel::Xposition => local.translation.x += value,
+                        BvhChannel::Yposition => local.translation.y += value,
+                        BvhChannel::Zposition => local.translation.z += value,
+                        BvhChannel::Xrotation => local.rotation *= Quat::from_rotation_x(angle),
+                        BvhChannel::Yrotation => local.rotation *= Quat::from_rotation_y(angle),
+                        BvhChannel::Zrotation => local.rotation *= Quat::from_rotation_z(angle),
+                    }
+                }
+                local
+            })
+            .collect())
+    }
+
+    /// World transform of every joint in `frame`.
+    pub fn world_transforms(&self, frame: usize) -> Result<Vec<Transform>, IkError> {
+        let mut world = self.local_transforms(frame)?;
+        // Parents always come before their children.
+        for i in 0..world.len() {
+            if let Some(parent) = self.joints[i].parent {
+                world[i] = world[parent].mul_transform(world[i]);
+            }
+        }
+        Ok(world)
+    }
+
+    /// Targets for a chain in `frame`, one for each `(bvh joint name, chain index)` pair.
+    pub fn targets(&self, frame: usize, mapping: &[(&str, usize)]) -> Result<Vec<Target>, IkError> {
+        let world = self.world_transforms(frame)?;
+        mapping
+            .iter()
+            .map(|(name, index)| {
+                let joint = self
+                    .joint(name)
+                    .ok_or_else(|| IkError::Bvh(format!("no joint named {name}")))?;
+                Ok(Target::new(*index, world[joint].translation))
+            })
+            .collect()
+    }
+
+    /// An empty clip with one joint per chain joint, named by `names` and laid out in the
+    /// chain's rest pose, ready for `push_pose`.
+    pub fn from_chain(
+        chain: &FabrikChain,
+        names: &[&str],
+        frame_time: Duration,
+    ) -> Result<Self, IkError> {
+        chain.validate()?;
+        if names.len() != chain.joints.len() {
+            return Err(IkError::Bvh(format!(
+                "chain has {} joints but {} names were given",
+                chain.joints.len(),
+                names.len()
+            )));
+        }
+        let rest = chain.initial_state.as_deref().unwrap_or(chain);
+        let rotation = vec![
+            BvhChannel::Zrotation,
+            BvhChannel::Xrotation,
+            BvhChannel::Yrotation,
+        ];
+        let joints = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| BvhJoint {
+                name: (*name).to_owned(),
+                parent: i.checked_sub(1),
+                offset: match i {
+                    0 => Vec3::ZERO,
+                    _ => rest.joints[i] - rest.joints[i - 1],
+                },
+                channels: match i {
+                    0 => [
+                        BvhChannel::Xposition,
+                        BvhChannel::Yposition,
+                        BvhChannel::Zposition,
+                    ]
+                    .into_iter()
+                    .chain(rotation.iter().copied())
+                    .collect(),
+                    _ => rotation.clone(),
+                },
+                end_site: (i + 1 == names.len()).then_some(Vec3::ZERO),
+            })
+            .collect();
+        Ok(Self {
+            joints,
+            frame_time,
+            frames: Vec::new(),
+        })
+    }
+
+    /// Appends the current pose of `chain`, which has to be the chain the clip was made from
+    /// with `from_chain`.
+    pub fn push_pose(&mut self, chain: &FabrikChain) -> Result<(), IkError> {
+        chain.validate()?;
+        if self.joints.len() != chain.joints.len() {
+            return Err(IkError::Bvh(format!(
+                "clip has {} joints but the chain has {}",
+                self.joints.len(),
+                chain.joints.len()
+            )));
+        }
+        // With every channel at zero the clip is in its rest pose, so each joint's world
+        // rotation is how far its segment frame has turned since then.
+        let mut rest_joints = vec![Vec3::ZERO];
+        for joint in &self.joints[1..] {
+            rest_joints.push(rest_joints[rest_joints.len() - 1] + joint.offset);
+        }
+        let turns = chain.turns_since(&rest_joints)?;
+
+        let mut values = Vec::new();
+        let mut parent = Quat::IDENTITY;
+        for (i, turn) in turns.into_iter().enumerate() {
+            if i == 0 {
+                values.extend(chain.joints[0].to_array());
+            }
+            let (z, x, y) = (parent.inverse() * turn).to_euler(EulerRot::ZXY);
+            values.extend([z, x, y].map(f32::to_degrees));
+            parent = turn;
+        }
+        self.frames.push(values);
+        Ok(())
+    }
+}
+
+impl fmt::Display for Bvh {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn write_joint(bvh: &Bvh, f: &mut fmt::Formatter<'_>, joint: usize) -> fmt::Result {
+            let depth = std::iter::successors(bvh.joints[joint].parent, |parent| {
+                bvh.joints[*parent].parent
+            })
+            .count();
+            let indent = "\t".repeat(depth);
+            let data = &bvh.joints[joint];
+            let kind = if data.parent.is_none() {
+                "ROOT"
+            } else {
+                "JOINT"
+            };
+            writeln!(f, "{indent}{kind} {}", data.name)?;
+            writeln!(f, "{indent}{{")?;
+            let offset = data.offset;
+            writeln!(f, "{indent}\tOFFSET {} {} {}", offset.x, offset.y, offset.z)?;
+            write!(f, "{indent}\tCHANNELS {}", data.channels.len())?;
+            for channel in &data.channels {
+                write!(f, " {channel}")?;
+            }
+            writeln!(f)?;
+            for child in (0..bvh.joints.len()).filter(|i| bvh.joints[*i].parent == Some(joint)) {
+                write_joint(bvh, f, child)?;
+            }
+            if let Some(end) = data.end_site {
+                writeln!(f, "{indent}\tEnd Site")?;
+                writeln!(f, "{indent}\t{{")?;
+                writeln!(f, "{indent}\t\tOFFSET {} {} {}", end.x, end.y, end.z)?;
+                writeln!(f, "{indent}\t}}")?;
+            }
+            writeln!(f, "{indent}}}")
+        }
+
+        writeln!(f, "HIERARCHY")?;
+        for root in (0..self.joints.len()).filter(|i| self.joints[*i].parent.is_none()) {
+            write_joint(self, f, root)?;
+        }
+        writeln!(f, "MOTION")?;
+        writeln!(f, "Frames: {}", self.frames.len())?;
+        writeln!(f, "Frame Time: {}", self.frame_time.as_secs_f64())?;
+        for frame in &self.frames {
+            let values: Vec<String> = frame.iter().map(f32::to_string).collect();
+            writeln!(f, "{}", values.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+fn keywords<'a>(
+    tokens: &mut impl Iterator<Item = &'a str>,
+    expected: &[&str],
+) -> Result<(), IkError> {
+    for word in expected {
+        if tokens.next() != Some(*word) {
+            return Err(IkError::Bvh(format!("expected {}", expected.join(" "))));
+        }
+    }
+    Ok(())
+}
+
+fn open_brace(token: Option<&str>) -> Result<(), IkError> {
+    match token {
+        Some("{") => Ok(()),
+        found => Err(IkError::Bvh(format!("expected {{, found {found:?}"))),
+    }
+}
+
+fn number(token: Option<&str>) -> Result<f32, IkError> {
+    let token = token.ok_or_else(|| IkError::Bvh("expected a number".into()))?;
+    token
+        .parse()
+        .map_err(|_| IkError::Bvh(format!("expected a number, found {token:?}")))
+}
@@ -0,0 +1,74 @@
+use crate::extern_prelude::*;
+
+/// A reading of the monotonic clock, as kept in `FabrikChain::prev_time`.
+#[cfg(feature = "std")]
+pub type Instant = std::time::Instant;
+
+/// Without `std` there is no clock to read, so no reading can exist and
+/// `FabrikChain::prev_time` stays `None`.
+#[cfg(not(feature = "std"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Instant {}
+
+/// Where the time between two solves comes from when computing angular velocities.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Clock {
+    /// Time actually elapsed between solves, read from the monotonic clock. The first solve
+    /// has nothing to measure against, so it reports no velocities.
+    #[cfg(feature = "std")]
+    Monotonic,
+    /// Every solve advances time by exactly this step, for simulations and tests.
+    FixedStep(Duration),
+}
+
+impl Default for Clock {
+    /// `Monotonic`. Without `std` there is no clock, and a zero `FixedStep` reports no
+    /// velocities until a real step is set.
+    fn default() -> Self {
+        #[cfg(feature = "std")]
+        return Clock::Monotonic;
+        #[cfg(not(feature = "std"))]
+        Clock::FixedStep(Duration::ZERO)
+    }
+}
+
+impl Clock {
+    /// Time since the previous frame, updating `prev_time` to this frame. `None` when there
+    /// is no previous frame to measure against.
+    #[cfg_attr(not(feature = "std"), allow(unused_variables))]
+    pub fn tick(&self, prev_time: &mut Option<Instant>) -> Option<Duration> {
+        match *self {
+            #[cfg(feature = "std")]
+            Clock::Monotonic => {
+                let now = Instant::now();
+                prev_time
+                    .replace(now)
+                    .map(|prev| now.saturating_duration_since(prev))
+            }
+            Clock::FixedStep(step) => Some(step),
+        }
+    }
+}
+
+/// Times a solve for `SolveReport::elapsed`, which stays zero without `std`.
+pub(crate) struct Stopwatch {
+    #[cfg(feature = "std")]
+    start: Instant,
+}
+
+impl Stopwatch {
+    pub(crate) fn start() -> Self {
+        Self {
+            #[cfg(feature = "std")]
+            start: Instant::now(),
+        }
+    }
+
+    pub(crate) fn elapsed(&self) -> Duration {
+        #[cfg(feature = "std")]
+        return self.start.elapsed();
+        #[cfg(not(feature = "std"))]
+        Duration::ZERO
+    }
+}
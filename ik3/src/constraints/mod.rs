@@ -0,0 +1,172 @@
+use crate::extern_prelude::*;
+use crate::{FabrikChain, IkError};
+use core::f64::consts::TAU;
+use glam::{DQuat, DVec3};
+
+/// Limits how far the segment leaving a joint may bend away from the segment entering it.
+///
+/// Angles are bend angles: `0.0` means the two segments are collinear.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum JointConstraint {
+    #[default]
+    Unconstrained,
+    /// Rotation only about `axis`, with the signed bend (right-handed about `axis`) kept in `min..=max`.
+    /// The range is taken round the circle, so it may cross ±π, and a range spanning `2π` or
+    /// more leaves the bend free.
+    ///
+    /// `axis` is in world space and does not turn with the parent segment: if the segment
+    /// leading into the joint twists, the hinge keeps bending about the same world axis. To
+    /// make it follow the limb, turn `axis` by the parent's frame from `segment_frames`
+    /// between solves.
+    Hinge { axis: Vec3, min: f32, max: f32 },
+    /// Free rotation, with the outgoing segment kept inside a cone of half-angle `max_swing`.
+    BallSocket { max_swing: f32 },
+}
+
+impl JointConstraint {
+    /// Pulls `direction` back inside the constraint, measured against the `reference` direction
+    /// of the parent segment. `reversed` is set when walking the chain from the tip to the root,
+    /// which flips the sign of a hinge bend.
+    pub fn apply(&self, reference: Vec3, direction: Vec3, reversed: bool) -> Vec3 {
+        let epsilon = f32::EPSILON as f64;
+        self.constrain(reference.as_dvec3(), direction.as_dvec3(), reversed, epsilon)
+            .as_vec3()
+    }
+
+    /// `apply` in double precision, for `DFabrikChain`.
+    pub fn apply_f64(&self, reference: DVec3, direction: DVec3, reversed: bool) -> DVec3 {
+        self.constrain(reference, direction, reversed, f64::EPSILON)
+    }
+
+    /// `apply` and `apply_f64` both work in double precision here, treating squared lengths
+    /// below `epsilon` as zero so single precision input keeps its own threshold.
+    fn constrain(
+        &self,
+        reference: DVec3,
+        direction: DVec3,
+        reversed: bool,
+        epsilon: f64,
+    ) -> DVec3 {
+        match *self {
+            JointConstraint::Unconstrained => direction,
+            JointConstraint::Hinge { axis, min, max } => {
+                let axis = axis.as_dvec3().normalize();
+                let (min, max) = (min as f64, max as f64);
+                let (min, max) = if reversed { (-max, -min) } else { (min, max) };
+                let ref_proj = reference - axis * reference.dot(axis);
+                if ref_proj.length_squared() < epsilon {
+                    return direction;
+                }
+                let ref_proj = ref_proj.normalize();
+                let dir_proj = direction - axis * direction.dot(axis);
+                let angle = if dir_proj.length_squared() < epsilon {
+                    0.0
+                } else {
+                    axis.dot(ref_proj.cross(dir_proj)).atan2(ref_proj.dot(dir_proj))
+                };
+                DQuat::from_axis_angle(axis, clamp_bend(angle, min, max)) * ref_proj
+            }
+            JointConstraint::BallSocket { max_swing } => {
+                let max_swing = max_swing as f64;
+                let angle = reference.angle_between(direction);
+                if angle <= max_swing {
+                    return direction;
+                }
+                let mut swing_axis = reference.cross(direction);
+                if swing_axis.length_squared() < epsilon {
+                    swing_axis = reference.any_orthonormal_vector();
+                }
+                DQuat::from_axis_angle(swing_axis.normalize(), max_swing) * reference.normalize()
+            }
+        }
+    }
+
+    /// Clamps an interior joint angle, as reported by `recalculate_angles` (`PI` when straight),
+    /// to the range this constraint allows. A hinge allows every unsigned bend between the
+    /// smallest and largest its range covers, taken round the circle as in `apply`.
+    pub fn clamp_angle(&self, angle: f32) -> f32 {
+        let (min_bend, max_bend) = match *self {
+            JointConstraint::Unconstrained => return angle,
+            JointConstraint::Hinge { min, max, .. } => {
+                let (min, max) = (min as f64, max as f64);
+                let ends = [unsigned_bend(min), unsigned_bend(max)];
+                let smallest = if covers(min, max, 0.0) {
+                    0.0
+                } else {
+                    ends[0].min(ends[1])
+                };
+                let largest = if covers(min, max, PI as f64) {
+                    PI as f64
+                } else {
+                    ends[0].max(ends[1])
+                };
+                (smallest as f32, largest as f32)
+            }
+            JointConstraint::BallSocket { max_swing } => (0.0, max_swing),
+        };
+        angle.clamp(PI - max_bend, PI - min_bend)
+    }
+}
+
+/// How far round the circle `angle` lies past `min`, in `0.0..TAU`.
+fn past(angle: f64, min: f64) -> f64 {
+    let past = (angle - min) % TAU;
+    if past < 0.0 {
+        past + TAU
+    } else {
+        past
+    }
+}
+
+/// Whether `min..=max`, taken round the circle, covers `angle`.
+fn covers(min: f64, max: f64, angle: f64) -> bool {
+    max - min >= TAU || past(angle, min) <= max - min
+}
+
+/// Size of the bend `angle` in `0.0..=PI`, whichever way round it goes.
+fn unsigned_bend(angle: f64) -> f64 {
+    let angle = past(angle, 0.0);
+    angle.min(TAU - angle)
+}
+
+/// `angle` if it lies in `min..=max` taken round the circle, otherwise whichever end of the
+/// range is nearer round the circle.
+fn clamp_bend(angle: f64, min: f64, max: f64) -> f64 {
+    if covers(min, max, angle) {
+        return angle;
+    }
+    let past_min = past(angle, min);
+    if past_min - (max - min) < TAU - past_min {
+        max
+    } else {
+        min
+    }
+}
+
+impl FabrikChain {
+    /// Sets the constraint on joint `index`. Constraints on the first and last joint have no
+    /// effect, since there is no parent segment to measure them against.
+    pub fn set_constraint(
+        &mut self,
+        index: usize,
+        constraint: JointConstraint,
+    ) -> Result<&mut Self, IkError> {
+        if index >= self.constraints.len() {
+            return Err(IkError::JointOutOfRange {
+                index,
+                len: self.constraints.len(),
+            });
+        }
+        self.constraints[index] = constraint;
+        for snapshot in [&mut self.initial_state, &mut self.fantasy_limb]
+            .into_iter()
+            .flatten()
+        {
+            if let Some(slot) = snapshot.constraints.get_mut(index) {
+                *slot = constraint;
+            }
+        }
+        Ok(self)
+    }
+}
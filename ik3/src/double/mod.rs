@@ -0,0 +1,241 @@
+use crate::clock::Stopwatch;
+use crate::extern_prelude::*;
+use crate::{FabrikChain, IkError, JointConstraint, SolveReport};
+use glam::DVec3;
+
+/// Tolerance a `DFabrikChain` starts with, far below what `f32` resolves on a long arm.
+const TOLERANCE: f64 = 1e-6;
+
+/// A chain solved with FABRIK in double precision, for calibration and offline planning on
+/// arms long enough that `f32` loses accuracy at full reach.
+///
+/// Runs the same passes as `FabrikChain` with the default solver, working out from `root`
+/// and honouring joint constraints, but only follows target positions: there are no
+/// orientation targets, anchor points or obstacles. Convert with `from_chain`, which refuses
+/// chains that rely on them, and hand the result back to the real-time chain with `apply_to`.
+#[derive(Debug, Clone)]
+pub struct DFabrikChain {
+    pub joints: Vec<DVec3>,
+    pub lengths: Vec<f64>,
+    pub constraints: Vec<JointConstraint>,
+    pub targets: Vec<(usize, DVec3)>,
+    /// Joint the passes work out from, as in `FabrikChain::root`.
+    pub root: usize,
+    /// Where `root` is pinned during solving, or `None` to let it move freely.
+    pub ground: Option<DVec3>,
+    pub tolerance: f64,
+}
+
+impl DFabrikChain {
+    /// A chain through `joints`, grounded where the first one is.
+    pub fn new(joints: Vec<DVec3>) -> Result<Self, IkError> {
+        if joints.is_empty() {
+            return Err(IkError::EmptyChain);
+        }
+        let mut lengths = Vec::with_capacity(joints.len() - 1);
+        for (i, pair) in joints.windows(2).enumerate() {
+            let length = pair[0].distance(pair[1]);
+            if length <= f64::EPSILON {
+                return Err(IkError::ZeroLengthSegment(i));
+            }
+            lengths.push(length);
+        }
+        Ok(Self {
+            ground: Some(joints[0]),
+            constraints: vec![JointConstraint::default(); joints.len()],
+            joints,
+            lengths,
+            targets: Vec::new(),
+            root: 0,
+            tolerance: TOLERANCE,
+        })
+    }
+
+    /// The double precision copy of `chain`, with its pose, lengths, constraints, target
+    /// positions and root. The root is pinned where `lock_ground` would pin it. The tolerance
+    /// is not taken from `chain`, whose `f32` tolerance would throw the extra precision away,
+    /// but starts at the same default as `new`.
+    ///
+    /// A chain with anything this one can't follow, i.e. anchor points other than one pinning
+    /// the root, weighted or oriented targets, or obstacles, is an
+    /// `IkError::DoublePrecisionUnsupported`.
+    pub fn from_chain(chain: &FabrikChain) -> Result<Self, IkError> {
+        chain.validate()?;
+        let pins_root = |index: usize| chain.lock_ground && index == chain.root;
+        if chain
+            .motion_heuristics
+            .anchor_points
+            .iter()
+            .any(|(index, _, _)| !pins_root(*index))
+        {
+            return Err(IkError::DoublePrecisionUnsupported("anchor points"));
+        }
+        if chain.targets.iter().any(|target| target.position_weight != 1.0) {
+            return Err(IkError::DoublePrecisionUnsupported("weighted targets"));
+        }
+        if chain.targets.iter().any(|target| target.orientation.is_some()) {
+            return Err(IkError::DoublePrecisionUnsupported("orientation targets"));
+        }
+        if !chain.environment.obstacles.is_empty() {
+            return Err(IkError::DoublePrecisionUnsupported("obstacles"));
+        }
+        Ok(Self {
+            joints: chain.joints.iter().map(|joint| joint.as_dvec3()).collect(),
+            lengths: chain.lengths.iter().map(|length| *length as f64).collect(),
+            constraints: chain.constraints.clone(),
+            targets: chain
+                .targets
+                .iter()
+                .map(|target| (target.index, target.position.as_dvec3()))
+                .collect(),
+            root: chain.root,
+            ground: chain.pinned_root().map(|root| root.as_dvec3()),
+            tolerance: TOLERANCE,
+        })
+    }
+
+    /// Moves `chain`'s joints to where this chain's are. Both chains need the same number of
+    /// joints.
+    pub fn apply_to(&self, chain: &mut FabrikChain) -> Result<(), IkError> {
+        if chain.joints.len() != self.joints.len() {
+            return Err(IkError::MismatchedJointCount {
+                expected: self.joints.len(),
+                found: chain.joints.len(),
+            });
+        }
+        for (joint, precise) in chain.joints.iter_mut().zip(&self.joints) {
+            *joint = precise.as_vec3();
+        }
+        chain.recalculate_segments()
+    }
+
+    pub fn targets_reached(&self) -> bool {
+        self.targets
+            .iter()
+            .all(|(index, pos)| self.joints[*index].distance(*pos) <= self.tolerance)
+    }
+
+    /// Places joint `i` along segment `i`, measured from joint `i + 1`.
+    fn place_down(&mut self, i: usize) {
+        let b = self.joints[i + 1];
+        let mut direction = (self.joints[i] - b).normalize();
+        if let (Some(constraint), Some(parent)) =
+            (self.constraints.get(i + 1), self.joints.get(i + 2))
+        {
+            direction = constraint.apply_f64((b - *parent).normalize(), direction, true);
+        }
+        self.joints[i] = b + direction * self.lengths[i];
+    }
+
+    /// Places joint `i + 1` along segment `i`, measured from joint `i`.
+    fn place_up(&mut self, i: usize) {
+        let a = self.joints[i];
+        let mut direction = (self.joints[i + 1] - a).normalize();
+        if let (Some(constraint), Some(parent)) = (
+            self.constraints.get(i),
+            i.checked_sub(1).map(|parent| self.joints[parent]),
+        ) {
+            direction = constraint.apply_f64((a - parent).normalize(), direction, false);
+        }
+        self.joints[i + 1] = a + direction * self.lengths[i];
+    }
+
+    pub fn fwd_reach(&mut self) {
+        let Some(last) = self.joints.len().checked_sub(1) else {
+            return;
+        };
+        let root = self.root.min(last);
+        for (index, pos) in self.targets.iter() {
+            self.joints[*index] = *pos;
+        }
+        for i in (root..last).rev() {
+            self.place_down(i);
+        }
+        let from_above = self.joints[root];
+        for i in 0..root {
+            self.place_up(i);
+        }
+        // As in `FabrikChain::fwd_reach`, a root partway along the chain goes where the side
+        // with targets put it, or halfway if both or neither have any, and the other side
+        // shifts with it to keep its length.
+        if root > 0 && root < last {
+            let from_below = self.joints[root];
+            let below = self.targets.iter().any(|(index, _)| *index < root);
+            let above = self.targets.iter().any(|(index, _)| *index > root);
+            let meeting = match (below, above) {
+                (true, false) => from_below,
+                (false, true) => from_above,
+                _ => from_above.lerp(from_below, 0.5),
+            };
+            for joint in &mut self.joints[..root] {
+                *joint += meeting - from_below;
+            }
+            for joint in &mut self.joints[root + 1..] {
+                *joint += meeting - from_above;
+            }
+            self.joints[root] = meeting;
+        }
+    }
+
+    pub fn bwd_reach(&mut self) {
+        let Some(last) = self.joints.len().checked_sub(1) else {
+            return;
+        };
+        let root = self.root.min(last);
+        if let Some(ground) = self.ground {
+            self.joints[root] = ground;
+        }
+        for i in root..last {
+            self.place_up(i);
+        }
+        for i in (0..root).rev() {
+            self.place_down(i);
+        }
+    }
+
+    /// Runs up to `iterations` passes, stopping once every target is within `tolerance`.
+    /// Residuals are reported in `f32`, which keeps their precision since they are small.
+    pub fn solve(&mut self, iterations: usize) -> Result<SolveReport, IkError> {
+        let len = self.joints.len();
+        if len == 0 {
+            return Err(IkError::EmptyChain);
+        }
+        if self.lengths.len() + 1 != len {
+            return Err(IkError::MismatchedLengths {
+                joints: len,
+                lengths: self.lengths.len(),
+            });
+        }
+        for index in self
+            .targets
+            .iter()
+            .map(|(index, _)| *index)
+            .chain([self.root])
+        {
+            if index >= len {
+                return Err(IkError::JointOutOfRange { index, len });
+            }
+        }
+        let start = Stopwatch::start();
+        let mut iterations_used = 0;
+        for _ in 0..iterations {
+            if self.targets_reached() {
+                break;
+            }
+            iterations_used += 1;
+            self.fwd_reach();
+            self.bwd_reach();
+        }
+        Ok(SolveReport {
+            iterations: iterations_used,
+            residuals: self
+                .targets
+                .iter()
+                .map(|(index, pos)| (*index, self.joints[*index].distance(*pos) as f32))
+                .collect(),
+            reached: self.targets_reached(),
+            elapsed: start.elapsed(),
+            ..Default::default()
+        })
+    }
+}
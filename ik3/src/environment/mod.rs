@@ -0,0 +1,226 @@
+use crate::extern_prelude::*;
+use crate::FabrikChain;
+
+/// Number of points sampled along a segment when checking it against obstacles.
+const SEGMENT_SAMPLES: usize = 8;
+/// How many times a single segment is swung away before giving up on it for this pass.
+const PUSH_ATTEMPTS: usize = 4;
+
+/// A solid shape the chain has to stay out of.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Obstacle {
+    Sphere { center: Vec3, radius: f32 },
+    /// Axis-aligned box between the `min` and `max` corners.
+    Aabb { min: Vec3, max: Vec3 },
+    /// Box of the given half extents, rotated by `rotation` about its `center`.
+    Obb {
+        center: Vec3,
+        half_extents: Vec3,
+        rotation: Quat,
+    },
+    /// Every point within `radius` of the segment from `start` to `end`.
+    Capsule { start: Vec3, end: Vec3, radius: f32 },
+    /// Everything behind the plane, i.e. on the opposite side to `normal`.
+    Plane { point: Vec3, normal: Vec3 },
+}
+
+impl Obstacle {
+    /// Returns the nearest point at least `margin` outside the obstacle, or `None` if `point`
+    /// is already clear of it.
+    pub fn push_out(&self, point: Vec3, margin: f32) -> Option<Vec3> {
+        match *self {
+            Obstacle::Sphere { center, radius } => push_out_sphere(center, radius + margin, point),
+            Obstacle::Aabb { min, max } => {
+                let center = (min + max) / 2.0;
+                push_out_box(center, (max - min) / 2.0, Quat::IDENTITY, margin, point)
+            }
+            Obstacle::Obb {
+                center,
+                half_extents,
+                rotation,
+            } => push_out_box(center, half_extents, rotation, margin, point),
+            Obstacle::Capsule { start, end, radius } => {
+                let axis = end - start;
+                let t = if axis.length_squared() < f32::EPSILON {
+                    0.0
+                } else {
+                    ((point - start).dot(axis) / axis.length_squared()).clamp(0.0, 1.0)
+                };
+                push_out_sphere(start + axis * t, radius + margin, point)
+            }
+            Obstacle::Plane { point: origin, normal } => {
+                let normal = normal.normalize();
+                let depth = (point - origin).dot(normal);
+                (depth < margin).then(|| point + normal * (margin - depth))
+            }
+        }
+    }
+}
+
+fn push_out_sphere(center: Vec3, radius: f32, point: Vec3) -> Option<Vec3> {
+    let offset = point - center;
+    if offset.length_squared() >= radius * radius {
+        return None;
+    }
+    let direction = offset.try_normalize().unwrap_or(Vec3::Y);
+    Some(center + direction * radius)
+}
+
+fn push_out_box(
+    center: Vec3,
+    half_extents: Vec3,
+    rotation: Quat,
+    margin: f32,
+    point: Vec3,
+) -> Option<Vec3> {
+    let local = rotation.inverse() * (point - center);
+    let half_extents = half_extents + Vec3::splat(margin);
+    let penetration = half_extents - local.abs();
+    if penetration.min_element() <= 0.0 {
+        return None;
+    }
+    // Leave through whichever face is nearest.
+    let mut pushed = local;
+    let axis = if penetration.x <= penetration.y && penetration.x <= penetration.z {
+        0
+    } else if penetration.y <= penetration.z {
+        1
+    } else {
+        2
+    };
+    pushed[axis] = half_extents[axis].copysign(local[axis]);
+    Some(center + rotation * pushed)
+}
+
+/// The obstacles around a chain, used by `PoseDiscrepancy::EnvironmentalCompensation`.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Environment {
+    pub obstacles: Vec<Obstacle>,
+    /// Extra clearance added when pushing the chain out of an obstacle, so it does not end
+    /// up grazing the surface.
+    pub margin: f32,
+}
+
+impl Environment {
+    pub fn new(obstacles: Vec<Obstacle>, margin: f32) -> Self {
+        Self { obstacles, margin }
+    }
+
+    /// Moves `point` out of every obstacle it is inside of.
+    pub fn resolve_point(&self, point: Vec3) -> Vec3 {
+        self.obstacles.iter().fold(point, |point, obstacle| {
+            obstacle.push_out(point, self.margin).unwrap_or(point)
+        })
+    }
+
+    /// Whether any point sampled along the segment from `a` to `b` is inside an obstacle.
+    pub fn segment_collides(&self, a: Vec3, b: Vec3) -> bool {
+        (0..=SEGMENT_SAMPLES).any(|sample| {
+            let point = a.lerp(b, sample as f32 / SEGMENT_SAMPLES as f32);
+            self.obstacles
+                .iter()
+                .any(|obstacle| obstacle.push_out(point, 0.0).is_some())
+        })
+    }
+
+    /// Displacement of `b` that swings the segment from `a` to `b` out of its deepest
+    /// penetration, keeping `a` fixed.
+    fn segment_correction(&self, a: Vec3, b: Vec3) -> Vec3 {
+        let mut correction = Vec3::ZERO;
+        for sample in 1..=SEGMENT_SAMPLES {
+            let t = sample as f32 / SEGMENT_SAMPLES as f32;
+            let point = a.lerp(b, t);
+            // Moving `b` by `d` only moves this point by `t * d`.
+            let needed = (self.resolve_point(point) - point) / t;
+            if needed.length_squared() > correction.length_squared() {
+                correction = needed;
+            }
+        }
+        correction
+    }
+}
+
+impl FabrikChain {
+    /// Number of segments that are inside an obstacle.
+    pub fn contacts(&self) -> usize {
+        self.joints
+            .windows(2)
+            .filter(|pair| self.environment.segment_collides(pair[0], pair[1]))
+            .count()
+    }
+
+    /// Walks out from the root in both directions, swinging every segment that is inside an
+    /// obstacle about its joint nearer the root. Everything beyond the segment turns with it,
+    /// so every segment keeps its length, and the swing respects the constraint at that joint.
+    /// The root never moves, and segments that would carry an anchor point with them are left
+    /// where they are. Returns how many segments were in contact.
+    pub fn avoid_obstacles(&mut self) -> usize {
+        let root = self.root.min(self.joints.len().saturating_sub(1));
+        (root..self.joints.len().saturating_sub(1))
+            .chain((0..root).rev())
+            .filter(|i| self.swing_clear(*i))
+            .count()
+    }
+
+    /// Swings segment `i` out of the obstacles, turning the joints beyond it rigidly about its
+    /// joint nearer the root. Returns whether it was in contact.
+    fn swing_clear(&mut self, i: usize) -> bool {
+        let outwards = i >= self.root;
+        let (pivot, end, beyond) = if outwards {
+            (i, i + 1, i + 1..self.joints.len())
+        } else {
+            (i + 1, i, 0..i + 1)
+        };
+        let a = self.joints[pivot];
+        if !self.environment.segment_collides(a, self.joints[end]) {
+            return false;
+        }
+        if beyond.clone().any(|j| self.anchor_position(j).is_some()) {
+            return true;
+        }
+        let parent = if outwards {
+            i.checked_sub(1)
+        } else {
+            Some(i + 2)
+        }
+        .and_then(|parent| self.joints.get(parent).copied());
+        for _ in 0..PUSH_ATTEMPTS {
+            let b = self.joints[end];
+            if !self.environment.segment_collides(a, b) {
+                break;
+            }
+            let pushed = self
+                .environment
+                .resolve_point(b + self.environment.segment_correction(a, b));
+            let current = (b - a).normalize();
+            let mut direction = (pushed - a).try_normalize().unwrap_or(current);
+            if let (Some(constraint), Some(parent)) = (self.constraints.get(pivot), parent) {
+                direction = constraint.apply((a - parent).normalize(), direction, !outwards);
+            }
+            let turn = Quat::from_rotation_arc(current, direction);
+            for j in beyond.clone() {
+                self.joints[j] = a + turn * (self.joints[j] - a);
+            }
+        }
+        true
+    }
+
+    /// Like `iterate`, but pushes the chain out of `environment` after every pass. The real
+    /// limb is allowed to diverge from the fantasy limb to get around obstacles; the fantasy
+    /// limb itself is left untouched. Returns the number of passes actually run.
+    pub(crate) fn iterate_avoiding(&mut self, iterations: usize) -> usize {
+        let mut iterations_used = 0;
+        self.avoid_obstacles();
+        for _ in 0..iterations {
+            if self.targets_reached() && self.contacts() == 0 {
+                break;
+            }
+            iterations_used += 1;
+            self.iterate(1);
+            self.avoid_obstacles();
+        }
+        iterations_used
+    }
+}
@@ -0,0 +1,114 @@
+use crate::extern_prelude::*;
+use core::fmt;
+
+/// Everything that can go wrong while building, solving or resetting a `FabrikChain`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum IkError {
+    /// The chain has no joints.
+    EmptyChain,
+    /// Segment `index` (between joints `index` and `index + 1`, or for a `Skeleton` between
+    /// joint `index` and its parent) has zero length.
+    ZeroLengthSegment(usize),
+    /// `joints` and `lengths` no longer describe the same chain.
+    MismatchedLengths { joints: usize, lengths: usize },
+    /// A chain of `joints` joints was given `constraints` joint constraints instead of one per
+    /// joint.
+    MismatchedConstraints { joints: usize, constraints: usize },
+    /// Forward kinematics was given a different number of rotations than there are segments.
+    MismatchedRotations { segments: usize, rotations: usize },
+    /// A chain with `found` joints was given where one with `expected` joints was needed.
+    MismatchedJointCount { expected: usize, found: usize },
+    /// A joint index, e.g. from a target or constraint, is past the end of the chain.
+    JointOutOfRange { index: usize, len: usize },
+    /// Joint `index` of a skeleton has no valid parent: only the first joint may be the root,
+    /// and every other joint's parent has to come before it.
+    InvalidParent(usize),
+    /// A `Skeleton` is always rooted at its first joint, so it can't be built from a chain
+    /// rooted at joint `root`.
+    UnsupportedRoot(usize),
+    /// The chain was cloned or built without its initial state, so it cannot be reset.
+    MissingInitialState,
+    /// The solve mode needs a fantasy limb, but the chain has none.
+    MissingFantasyLimb,
+    /// The fantasy limb has a different number of joints than the real one.
+    FantasyLimbMismatch { joints: usize, fantasy_joints: usize },
+    /// A `DFabrikChain` can't be built from a chain using this, which it would otherwise drop.
+    DoublePrecisionUnsupported(&'static str),
+    /// The requested `PoseDiscrepancy` mode is not implemented.
+    UnsupportedMode(&'static str),
+    /// A rig file could not be read or written.
+    Rig(String),
+    /// The rig was written by a newer version of the format than this build understands.
+    UnsupportedRigVersion { found: u32, supported: u32 },
+    /// A URDF could not be parsed, or does not describe the requested chain.
+    Urdf(String),
+    /// A glTF file could not be read or written, or does not contain the requested joints.
+    Gltf(String),
+    /// A BVH clip could not be parsed, or does not match the chain it is used with.
+    Bvh(String),
+    /// An `IkChain`'s bones or target can't be posed, or don't match its chain.
+    Bevy(String),
+}
+
+impl fmt::Display for IkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IkError::EmptyChain => write!(f, "chain has no joints"),
+            IkError::ZeroLengthSegment(index) => write!(f, "segment {index} has zero length"),
+            IkError::MismatchedLengths { joints, lengths } => write!(
+                f,
+                "chain has {joints} joints but {lengths} segment lengths"
+            ),
+            IkError::MismatchedConstraints {
+                joints,
+                constraints,
+            } => write!(
+                f,
+                "chain has {joints} joints but {constraints} joint constraints"
+            ),
+            IkError::MismatchedRotations {
+                segments,
+                rotations,
+            } => write!(
+                f,
+                "chain has {segments} segments but {rotations} joint rotations were given"
+            ),
+            IkError::MismatchedJointCount { expected, found } => write!(
+                f,
+                "expected a chain of {expected} joints but it has {found}"
+            ),
+            IkError::JointOutOfRange { index, len } => {
+                write!(f, "joint index {index} out of range for chain of {len} joints")
+            }
+            IkError::InvalidParent(index) => write!(f, "joint {index} has an invalid parent"),
+            IkError::UnsupportedRoot(root) => {
+                write!(f, "a skeleton can't be rooted at joint {root}, only at joint 0")
+            }
+            IkError::MissingInitialState => write!(f, "chain has no initial state to reset to"),
+            IkError::MissingFantasyLimb => write!(f, "chain has no fantasy limb"),
+            IkError::FantasyLimbMismatch {
+                joints,
+                fantasy_joints,
+            } => write!(
+                f,
+                "chain has {joints} joints but its fantasy limb has {fantasy_joints}"
+            ),
+            IkError::DoublePrecisionUnsupported(what) => {
+                write!(f, "double precision chains don't support {what}")
+            }
+            IkError::UnsupportedMode(mode) => write!(f, "solve mode {mode} is not supported"),
+            IkError::Rig(message) => write!(f, "invalid rig: {message}"),
+            IkError::Urdf(message) => write!(f, "invalid URDF: {message}"),
+            IkError::Gltf(message) => write!(f, "invalid glTF: {message}"),
+            IkError::Bvh(message) => write!(f, "invalid BVH: {message}"),
+            IkError::Bevy(message) => write!(f, "invalid IK chain: {message}"),
+            IkError::UnsupportedRigVersion { found, supported } => write!(
+                f,
+                "rig format version {found} is not supported, this build reads version {supported}"
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for IkError {}
@@ -1,6 +1,148 @@
 use crate::extern_prelude::*;
-use crate::FabrikChain;
+use crate::{FabrikChain, IkError, BONE_AXIS};
 
 impl FabrikChain {
-    pub fn procedural_parenting(&mut self) {}
+    /// Moves the root to the planted joint with the highest `parent_ranking` priority, so the
+    /// solver reaches outwards from it. The new root is grounded where it currently is, so
+    /// switching never moves the pose. Returns whether the root changed.
+    pub fn procedural_parenting(&mut self) -> bool {
+        let ranking = |joint: usize| {
+            self.motion_heuristics
+                .parent_ranking
+                .iter()
+                .find(|(ranked, _, _)| *ranked == joint)
+                .map(|(_, priority, hysteresis)| (*priority, *hysteresis))
+                .unwrap_or_default()
+        };
+        let best = self
+            .motion_heuristics
+            .anchor_points
+            .iter()
+            .map(|(joint, _, _)| *joint)
+            .filter(|joint| *joint < self.joints.len())
+            // Highest priority wins, ties go to the joint nearest the start of the chain.
+            .max_by_key(|joint| (ranking(*joint).0, core::cmp::Reverse(*joint)));
+        let Some(best) = best else {
+            return false;
+        };
+        if best == self.root {
+            return false;
+        }
+        let (current_priority, current_hysteresis) = ranking(self.root);
+        let root_planted = self.anchor_position(self.root).is_some();
+        if root_planted && ranking(best).0 <= current_priority + current_hysteresis {
+            return false;
+        }
+        self.root = best;
+        self.ground = self.joints[best];
+        true
+    }
+
+    /// Frame at the root, turning `BONE_AXIS` onto `up`.
+    pub fn base_frame(&self) -> Quat {
+        Quat::from_rotation_arc(BONE_AXIS, self.up.normalize())
+    }
+
+    /// World-space frame of every segment, with `BONE_AXIS` along the segment. Each frame is
+    /// carried over from its parent by the smallest rotation that lines it up with the next
+    /// segment, starting from `base_frame` at the root.
+    pub fn segment_frames(&self) -> Vec<Quat> {
+        frames(&self.joints, self.base_frame())
+    }
+
+    /// World-space rotation each joint has turned through since the chain lay along `rest`:
+    /// the rotation taking its segment's frame in `rest` onto its current one, both carried
+    /// from `base_frame`. The tip has no segment of its own and turns with the one leading
+    /// to it. Posing bones is then a matter of turning each by its entry.
+    #[cfg(any(feature = "bevy", feature = "bevy_transform", feature = "gltf"))]
+    pub(crate) fn turns_since(&self, rest: &[Vec3]) -> Result<Vec<Quat>, IkError> {
+        if rest.len() != self.joints.len() {
+            return Err(IkError::MismatchedJointCount {
+                expected: self.joints.len(),
+                found: rest.len(),
+            });
+        }
+        if let Some(index) = rest
+            .windows(2)
+            .position(|pair| pair[0].distance(pair[1]) <= f32::EPSILON)
+        {
+            return Err(IkError::ZeroLengthSegment(index));
+        }
+        let mut turns: Vec<Quat> = self
+            .segment_frames()
+            .into_iter()
+            .zip(frames(rest, self.base_frame()))
+            .map(|(frame, rest_frame)| (frame * rest_frame.inverse()).normalize())
+            .collect();
+        turns.push(turns.last().copied().unwrap_or(Quat::IDENTITY));
+        Ok(turns)
+    }
+
+    /// Rotation of each segment relative to its parent segment's frame, or relative to
+    /// `base_frame` for the first segment. Entry `i` is the rotation at joint `i`.
+    pub fn joint_rotations(&self) -> Vec<Quat> {
+        let mut parent = self.base_frame();
+        self.segment_frames()
+            .into_iter()
+            .map(|frame| {
+                let local = parent.inverse() * frame;
+                parent = frame;
+                local
+            })
+            .collect()
+    }
+
+    /// Forward kinematics: lays the chain out using one local rotation per segment, as
+    /// returned by `joint_rotations`, and recomputes angles and segment transforms. The root
+    /// stays where it is pinned, or where it is if it is free, and the segments are laid out
+    /// from it in both directions.
+    pub fn set_joint_rotations(&mut self, rotations: &[Quat]) -> Result<(), IkError> {
+        self.validate()?;
+        if rotations.len() != self.lengths.len() {
+            return Err(IkError::MismatchedRotations {
+                segments: self.lengths.len(),
+                rotations: rotations.len(),
+            });
+        }
+        let mut frame = self.base_frame();
+        let directions: Vec<Vec3> = rotations
+            .iter()
+            .map(|rotation| {
+                frame = (frame * *rotation).normalize();
+                frame * BONE_AXIS
+            })
+            .collect();
+        let root = self.root;
+        self.joints[root] = self.pinned_root().unwrap_or(self.joints[root]);
+        for (i, direction) in directions.iter().enumerate().skip(root) {
+            self.joints[i + 1] = self.joints[i] + *direction * self.lengths[i];
+        }
+        for (i, direction) in directions.iter().enumerate().take(root).rev() {
+            self.joints[i] = self.joints[i + 1] - *direction * self.lengths[i];
+        }
+        self.recalculate_angles();
+        self.recalculate_segments()
+    }
+
+    /// Forward kinematics from an angle about an axis at every joint, the axis being given in
+    /// the parent segment's frame.
+    pub fn set_joint_angles(&mut self, axis_angles: &[(Vec3, f32)]) -> Result<(), IkError> {
+        let rotations: Vec<Quat> = axis_angles
+            .iter()
+            .map(|(axis, angle)| Quat::from_axis_angle(axis.normalize(), *angle))
+            .collect();
+        self.set_joint_rotations(&rotations)
+    }
+}
+
+/// Frame of every segment between `joints`, carried from `base` as in `segment_frames`.
+fn frames(joints: &[Vec3], base: Quat) -> Vec<Quat> {
+    let mut frames = Vec::with_capacity(joints.len().saturating_sub(1));
+    let mut frame = base;
+    for pair in joints.windows(2) {
+        let direction = (pair[1] - pair[0]).normalize();
+        frame = (Quat::from_rotation_arc(frame * BONE_AXIS, direction) * frame).normalize();
+        frames.push(frame);
+    }
+    frames
 }
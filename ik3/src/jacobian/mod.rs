@@ -0,0 +1,261 @@
+use crate::extern_prelude::*;
+use crate::solver::{beyond, constrain, rotate};
+use crate::{FabrikChain, JointConstraint, Solver, SolverKind, BONE_AXIS};
+
+/// Damped least squares: treats every joint as one rotation per degree of freedom (one about
+/// the axis of a hinge, three otherwise), and each pass takes the joint-angle step that best
+/// moves the targets towards their goals, `Δθ = Jᵀ (J Jᵀ + λ² I)⁻¹ e`.
+///
+/// Target positions and orientations, and any anchor points away from the root, all become
+/// rows of the Jacobian `J`, weighted by their target weights. After each step the joints are
+/// pulled back inside their constraints, so hinges only ever turn about their axis and stay
+/// within their limits.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DampedLeastSquares {
+    /// `λ`. Larger values take smaller, steadier steps and keep the chain calm near
+    /// singularities such as full extension; smaller values converge faster when well away
+    /// from them.
+    pub damping: f32,
+}
+
+impl Default for DampedLeastSquares {
+    fn default() -> Self {
+        Self { damping: 0.1 }
+    }
+}
+
+impl DampedLeastSquares {
+    pub fn new(damping: f32) -> Self {
+        Self { damping }
+    }
+}
+
+/// One degree of freedom: turning the joints `beyond` `pivot` about `axis`.
+struct Column {
+    pivot: usize,
+    upward: bool,
+    axis: Vec3,
+}
+
+impl Column {
+    /// Whether turning this column moves joint `index`.
+    fn moves_joint(&self, index: usize) -> bool {
+        if self.upward {
+            index > self.pivot
+        } else {
+            index < self.pivot
+        }
+    }
+
+    /// Whether turning this column turns `segment`.
+    fn turns_segment(&self, segment: usize) -> bool {
+        if self.upward {
+            segment >= self.pivot
+        } else {
+            segment < self.pivot
+        }
+    }
+}
+
+/// Three rows of the Jacobian: where something should be, and how far off it is.
+enum Task {
+    /// Move joint `index` by `error`.
+    Position {
+        index: usize,
+        error: Vec3,
+        weight: f32,
+    },
+    /// Turn `segment`, whose direction is `direction`, by the rotation vector `error`.
+    Direction {
+        segment: usize,
+        direction: Vec3,
+        error: Vec3,
+        weight: f32,
+    },
+}
+
+impl Solver for DampedLeastSquares {
+    fn step(&self, chain: &mut FabrikChain) {
+        if let Some(root) = chain.pinned_root() {
+            let offset = root - chain.joints[chain.root];
+            for joint in chain.joints.iter_mut() {
+                *joint += offset;
+            }
+        }
+
+        let columns = columns(chain);
+        let tasks = tasks(chain);
+        if columns.is_empty() || tasks.is_empty() {
+            return;
+        }
+        // Jacobian, one row per task component and one column per degree of freedom.
+        let (rows, cols) = (tasks.len() * 3, columns.len());
+        let mut jacobian = vec![0.0f64; rows * cols];
+        let mut error = vec![0.0f64; rows];
+        for (t, task) in tasks.iter().enumerate() {
+            let (task_error, weight) = match task {
+                Task::Position { error, weight, .. } | Task::Direction { error, weight, .. } => {
+                    (*error, *weight)
+                }
+            };
+            for (c, column) in columns.iter().enumerate() {
+                let derivative = match *task {
+                    Task::Position { index, .. } if column.moves_joint(index) => column
+                        .axis
+                        .cross(chain.joints[index] - chain.joints[column.pivot]),
+                    // Twist about the segment does not change where it points.
+                    Task::Direction {
+                        segment, direction, ..
+                    } if column.turns_segment(segment) => {
+                        column.axis - direction * column.axis.dot(direction)
+                    }
+                    _ => Vec3::ZERO,
+                };
+                for k in 0..3 {
+                    jacobian[(t * 3 + k) * cols + c] = (derivative[k] * weight) as f64;
+                }
+            }
+            for k in 0..3 {
+                error[t * 3 + k] = (task_error[k] * weight) as f64;
+            }
+        }
+
+        // (J Jᵀ + λ² I) y = e, then Δθ = Jᵀ y.
+        let damping = (self.damping as f64).powi(2);
+        let mut normal = vec![0.0f64; rows * rows];
+        for i in 0..rows {
+            for j in 0..=i {
+                let dot: f64 = (0..cols)
+                    .map(|c| jacobian[i * cols + c] * jacobian[j * cols + c])
+                    .sum();
+                normal[i * rows + j] = dot;
+                normal[j * rows + i] = dot;
+            }
+            normal[i * rows + i] += damping;
+        }
+        let Some(y) = cholesky_solve(&mut normal, error, rows) else {
+            return;
+        };
+
+        // Outermost joints first, so every turn is about where its pivot was linearised.
+        let order = columns
+            .iter()
+            .enumerate()
+            .filter(|(_, column)| column.upward)
+            .rev()
+            .chain(
+                columns
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, column)| !column.upward),
+            );
+        for (c, column) in order {
+            let angle: f64 = (0..rows).map(|r| jacobian[r * cols + c] * y[r]).sum();
+            let center = chain.joints[column.pivot];
+            let moving = beyond(chain, column.pivot, column.upward);
+            rotate(
+                chain,
+                moving,
+                center,
+                Quat::from_axis_angle(column.axis, angle as f32),
+            );
+        }
+
+        for pivot in chain.root..chain.joints.len() - 1 {
+            constrain(chain, pivot, true);
+        }
+        for pivot in (1..=chain.root).rev() {
+            constrain(chain, pivot, false);
+        }
+    }
+
+    fn kind(&self) -> Option<SolverKind> {
+        Some(SolverKind::DampedLeastSquares(*self))
+    }
+}
+
+fn columns(chain: &FabrikChain) -> Vec<Column> {
+    let mut columns = Vec::new();
+    for pivot in 0..chain.joints.len() {
+        let upward = pivot >= chain.root;
+        if beyond(chain, pivot, upward).is_empty() {
+            continue;
+        }
+        match chain.constraints.get(pivot) {
+            Some(JointConstraint::Hinge { axis, .. }) => columns.push(Column {
+                pivot,
+                upward,
+                axis: axis.normalize(),
+            }),
+            _ => columns.extend([Vec3::X, Vec3::Y, Vec3::Z].map(|axis| Column {
+                pivot,
+                upward,
+                axis,
+            })),
+        }
+    }
+    columns
+}
+
+fn tasks(chain: &FabrikChain) -> Vec<Task> {
+    let mut tasks = Vec::new();
+    for target in &chain.targets {
+        tasks.push(Task::Position {
+            index: target.index,
+            error: target.position - chain.joints[target.index],
+            weight: target.position_weight,
+        });
+        // A single-joint chain has no segment to turn.
+        let segment = chain.orientation_segment(target.index);
+        if let (Some(orientation), Some(segment)) = (target.orientation, segment) {
+            let direction = (chain.joints[segment + 1] - chain.joints[segment]).normalize();
+            let goal = orientation * BONE_AXIS;
+            let axis = direction.cross(goal);
+            let error = axis.try_normalize().unwrap_or(Vec3::ZERO)
+                * axis.length().atan2(direction.dot(goal));
+            tasks.push(Task::Direction {
+                segment,
+                direction,
+                error,
+                weight: target.orientation_weight,
+            });
+        }
+    }
+    for (index, position, _) in &chain.motion_heuristics.anchor_points {
+        if *index != chain.root {
+            tasks.push(Task::Position {
+                index: *index,
+                error: *position - chain.joints[*index],
+                weight: 1.0,
+            });
+        }
+    }
+    tasks
+}
+
+/// Solves `a x = b` for a symmetric positive definite `n` by `n` matrix, overwriting `a`
+/// with its Cholesky factor.
+fn cholesky_solve(a: &mut [f64], mut b: Vec<f64>, n: usize) -> Option<Vec<f64>> {
+    for j in 0..n {
+        let diagonal = a[j * n + j] - (0..j).map(|k| a[j * n + k].powi(2)).sum::<f64>();
+        if diagonal <= 0.0 {
+            return None;
+        }
+        let diagonal = diagonal.sqrt();
+        a[j * n + j] = diagonal;
+        for i in j + 1..n {
+            let sum: f64 = (0..j).map(|k| a[i * n + k] * a[j * n + k]).sum();
+            a[i * n + j] = (a[i * n + j] - sum) / diagonal;
+        }
+    }
+    for i in 0..n {
+        let sum: f64 = (0..i).map(|k| a[i * n + k] * b[k]).sum();
+        b[i] = (b[i] - sum) / a[i * n + i];
+    }
+    for i in (0..n).rev() {
+        let sum: f64 = (i + 1..n).map(|k| a[k * n + i] * b[k]).sum();
+        b[i] = (b[i] - sum) / a[i * n + i];
+    }
+    Some(b)
+}
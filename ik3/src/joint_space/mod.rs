@@ -0,0 +1,66 @@
+use crate::extern_prelude::*;
+use crate::{FabrikChain, IkError, BONE_AXIS};
+
+/// Signed angles of one joint, in radians, in the frame of the parent segment: `pitch` about
+/// its X axis, `yaw` about its Z axis and `roll` about the segment itself (`BONE_AXIS`).
+///
+/// The rotation is split into a twist about the segment and a swing that tilts it, so pitch
+/// and yaw never pick up any of the roll, and all three keep the direction of the bend.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct JointAngles {
+    pub pitch: f32,
+    pub yaw: f32,
+    pub roll: f32,
+}
+
+impl JointAngles {
+    /// Swing-twist decomposition of a local joint rotation.
+    pub fn from_rotation(rotation: Quat) -> Self {
+        let rotation = rotation.normalize();
+        let along = BONE_AXIS * rotation.xyz().dot(BONE_AXIS);
+        let twist = Quat::from_xyzw(along.x, along.y, along.z, rotation.w);
+        let twist = if twist.length_squared() < f32::EPSILON {
+            // A half turn swing with no twist at all.
+            Quat::IDENTITY
+        } else {
+            twist.normalize()
+        };
+        let swing = shortest(rotation * twist.inverse()).to_scaled_axis();
+        let (axis, roll) = shortest(twist).to_axis_angle();
+        Self {
+            pitch: swing.x,
+            yaw: swing.z,
+            roll: roll * axis.dot(BONE_AXIS).signum(),
+        }
+    }
+
+    pub fn to_rotation(&self) -> Quat {
+        let swing = Quat::from_scaled_axis(Vec3::new(self.pitch, 0.0, self.yaw));
+        swing * Quat::from_axis_angle(BONE_AXIS, self.roll)
+    }
+}
+
+/// The same rotation with a non-negative `w`, so its angle stays within `-PI..=PI`.
+fn shortest(rotation: Quat) -> Quat {
+    if rotation.w < 0.0 {
+        -rotation
+    } else {
+        rotation
+    }
+}
+
+impl FabrikChain {
+    /// Signed joint angles of the current pose, one per segment, ready to send to actuators.
+    pub fn joint_angles(&self) -> Vec<JointAngles> {
+        self.joint_rotations()
+            .into_iter()
+            .map(JointAngles::from_rotation)
+            .collect()
+    }
+
+    /// Forward kinematics from signed joint angles, as returned by `joint_angles`.
+    pub fn set_joint_space(&mut self, angles: &[JointAngles]) -> Result<(), IkError> {
+        let rotations: Vec<Quat> = angles.iter().map(JointAngles::to_rotation).collect();
+        self.set_joint_rotations(&rotations)
+    }
+}
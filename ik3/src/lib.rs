@@ -1,26 +1,95 @@
+//! Inverse kinematics for chains of joints: FABRIK, CCD and damped least squares passes,
+//! joint constraints, and the angles and frames of the solved segments.
+//!
+//! The `std` feature is on by default. Without it the crate is `no_std` + `alloc` and needs
+//! the `libm` feature for its float math; timing, file formats and Bevy's `Transform` (the
+//! `bevy_transform` feature) are then unavailable. Check that build, and the `no_std` test
+//! that only runs with it, with `cargo test -p ik3 --no-default-features --features libm`
+//! (bacon's `test-no-std` job).
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(not(any(feature = "std", feature = "libm")))]
+compile_error!("ik3 needs either the `std` or the `libm` feature for its float math");
+
+extern crate alloc;
+
+mod batch;
+#[cfg(feature = "bevy_transform")]
+mod bvh;
+mod clock;
+mod constraints;
+mod double;
+mod environment;
+mod error;
 mod fk;
+mod jacobian;
+mod joint_space;
+mod math;
+#[cfg(feature = "bevy")]
+mod plugin;
+mod reach;
+mod recovery;
+mod report;
+mod rig;
+mod skeleton;
+mod solver;
+#[cfg(feature = "gltf")]
+mod skin;
+mod targets;
+#[cfg(feature = "urdf")]
+mod urdf;
 
 mod extern_prelude {
-    pub use std::{
-        assert_eq,
-        time::{Duration, SystemTime},
-    };
+    pub use alloc::{boxed::Box, string::String, sync::Arc, vec, vec::Vec};
+    pub use core::{f32::consts::PI, time::Duration};
 
-    pub use bevy_math::{Mat3, Quat, Vec3};
+    pub use crate::clock::Instant;
+    #[cfg(not(feature = "std"))]
+    pub use crate::math::Float;
+    #[cfg(feature = "bevy_transform")]
     pub use bevy_transform::prelude::Transform;
+    pub use glam::{Quat, Vec3};
 }
 
+use clock::Stopwatch;
+use core::cmp::Ordering;
 use extern_prelude::*;
+
+pub use batch::ChainBatch;
+#[cfg(feature = "bevy_transform")]
+pub use bvh::{Bvh, BvhChannel, BvhJoint};
+pub use clock::Clock;
+pub use constraints::JointConstraint;
+pub use double::DFabrikChain;
+pub use environment::{Environment, Obstacle};
+pub use error::IkError;
+pub use jacobian::DampedLeastSquares;
+pub use joint_space::JointAngles;
+#[cfg(feature = "bevy")]
+pub use plugin::{IkChain, IkPlugin, IkSet};
+pub use recovery::{Recovery, RecoverySettings, RecoveryStrategy};
+pub use report::SolveReport;
+pub use rig::{Rig, SolverSettings, RIG_VERSION};
+pub use skeleton::Skeleton;
+pub use solver::{Ccd, Fabrik, Solver, SolverKind};
+#[cfg(feature = "gltf")]
+pub use skin::GltfSkeleton;
+pub use targets::{Target, BONE_AXIS};
+#[cfg(feature = "urdf")]
+pub use urdf::{Urdf, UrdfJoint, UrdfJointKind};
+
 #[derive(Default)]
 pub enum PoseDiscrepancy {
     #[default]
     WithinTolerance,
+    /// Not implemented yet: `solve` returns `IkError::UnsupportedMode`.
     MildDivergence,
     SevereDivergence,
     EnvironmentalCompensation,
 }
 
-#[derive(Default)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub enum KinematicsMode {
     #[default]
     InverseKinematics,
@@ -31,14 +100,19 @@ pub enum KinematicsMode {
 type AnchorPoints = Vec<(usize, Vec3, Quat)>;
 type ParentRanking = Vec<(usize, i32, i32)>;
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MotionHeuristics {
+    /// Planted joints: `(joint, position, rotation)`.
     pub anchor_points: AnchorPoints,
+    /// `(joint, priority, hysteresis)`. Among the planted joints, the one with the highest
+    /// priority becomes the root. The current root keeps its place until another planted
+    /// joint beats its priority plus its hysteresis. Unranked joints have priority `0`.
     pub parent_ranking: ParentRanking,
 }
 
 impl MotionHeuristics {
-    fn new(anchor_points: AnchorPoints, parent_ranking: ParentRanking) -> Self {
+    pub fn new(anchor_points: AnchorPoints, parent_ranking: ParentRanking) -> Self {
         Self {
             anchor_points,
             parent_ranking,
@@ -50,49 +124,90 @@ impl MotionHeuristics {
 pub struct FabrikChain {
     pub joints: Vec<Vec3>,
     pub lengths: Vec<f32>,
+    pub constraints: Vec<JointConstraint>,
+    #[cfg(feature = "bevy_transform")]
     pub segment_transforms: Vec<Transform>,
     pub angles: Vec<f32>,
     pub prev_angles: Vec<f32>,
+    /// Change in each angle since the previous solve, in radians per second.
     pub angular_velocities: Vec<f32>,
-    pub targets: Vec<(usize, Vec3)>,
+    pub targets: Vec<Target>,
     pub motion_heuristics: MotionHeuristics,
-    pub prev_time: SystemTime,
+    /// Time of the previous solve, `None` until the first one so it can be skipped.
+    pub prev_time: Option<Instant>,
+    pub clock: Clock,
     pub lock_ground: bool,
+    /// Joint the solver reaches out from. Moved to the planted joint by `procedural_parenting`.
+    pub root: usize,
+    /// Where `lock_ground` pins the root, unless it has an anchor point of its own.
+    pub ground: Vec3,
+    /// Direction the frame at the root points along, which every segment frame is carried
+    /// on from. Segment frames only get unstable when the first segment points directly
+    /// against it, so set it to a direction the first segment always roughly points along.
+    pub up: Vec3,
+    /// Distance below which a target counts as reached, letting `solve` stop early.
+    pub tolerance: f32,
+    /// Angle, in radians, below which an oriented target counts as facing the right way.
+    pub orientation_tolerance: f32,
+    pub recovery: RecoverySettings,
+    pub environment: Environment,
+    /// Method `solve` runs each pass with. `Fabrik` unless set otherwise.
+    pub solver: Arc<dyn Solver>,
     pub fantasy_limb: Option<Box<Self>>,
-    // FIXME: first reading computation will be way off, start with prev_time option being none, and set it to some
-    // so as to skip the first computation frame
     initial_state: Option<Box<Self>>,
 }
 
 impl FabrikChain {
-    pub fn new(joints: Vec<Vec3>, motion_heuristics: MotionHeuristics) -> Self {
+    pub fn new(joints: Vec<Vec3>, motion_heuristics: MotionHeuristics) -> Result<Self, IkError> {
+        if joints.is_empty() {
+            return Err(IkError::EmptyChain);
+        }
         let mut lengths = Vec::new();
         for i in 1..joints.len() {
             let length = joints[i].distance(joints[i - 1]);
+            if length <= f32::EPSILON {
+                return Err(IkError::ZeroLengthSegment(i - 1));
+            }
             lengths.push(length);
         }
-        let new_self = Self {
+        let settings = SolverSettings::default();
+        let mut new_self = Self {
+            constraints: vec![JointConstraint::default(); joints.len()],
             joints,
             lengths,
             prev_angles: Vec::new(),
             angles: Vec::new(),
             angular_velocities: Vec::new(),
-            prev_time: std::time::SystemTime::now(),
+            prev_time: None,
+            clock: settings.clock,
             initial_state: None,
+            #[cfg(feature = "bevy_transform")]
             segment_transforms: Vec::new(),
             motion_heuristics,
             targets: Vec::new(),
-            lock_ground: true,
+            lock_ground: settings.lock_ground,
+            root: settings.root,
+            ground: settings.ground,
+            up: settings.up,
+            tolerance: settings.tolerance,
+            orientation_tolerance: settings.orientation_tolerance,
+            recovery: settings.recovery,
+            environment: settings.environment,
+            solver: Arc::new(Fabrik),
             fantasy_limb: None,
         };
+        new_self.snapshot();
+        new_self.recalculate_segments()?;
+        Ok(new_self)
+    }
 
-        let mut final_self = Self {
-            fantasy_limb: Some(Box::new(new_self.clone())),
-            initial_state: Some(Box::new(new_self.clone())),
-            ..new_self
-        };
-        final_self.recalculate_segments();
-        final_self
+    /// Makes the current pose the one `reset` returns to and the fantasy limb starts from.
+    pub(crate) fn snapshot(&mut self) {
+        self.fantasy_limb = None;
+        self.initial_state = None;
+        let snapshot = Box::new(self.clone());
+        self.fantasy_limb = Some(snapshot.clone());
+        self.initial_state = Some(snapshot);
     }
 
     pub fn finalize(&mut self) -> &mut Self {
@@ -103,143 +218,295 @@ impl FabrikChain {
         self
     }
 
-    pub fn get_ee(&self) -> &Vec3 {
-        self.joints.last().expect("Joints should not be empty")
+    pub fn get_ee(&self) -> Result<&Vec3, IkError> {
+        self.joints.last().ok_or(IkError::EmptyChain)
     }
 
-    pub fn recalculate_segments(&mut self) {
-        let frame_delta_time = self
-            .prev_time
-            .elapsed()
-            .expect("Could not get elapsed time");
-        self.prev_time = std::time::SystemTime::now();
-
-        self.angular_velocities.clear();
-        for i in 0..self.prev_angles.len() {
-            self.angular_velocities.push(
-                (self.angles[i] - self.prev_angles[i]) / (frame_delta_time.as_micros() as f32),
-            );
-        }
-        self.segment_transforms.clear();
-        for i in 1..self.joints.len() {
-            let a = self.joints[i];
-            let b = self.joints[i - 1];
-
-            let ab_vector = b - a;
-            let ab_vector = ab_vector.normalize();
-
-            let world_axis = Vec3::new(0.0, 1.0, 0.0); // Y-axis as an example
-
-            // Cross to get a perpendicular vector
-            let perp_vector = ab_vector.cross(world_axis).normalize();
+    /// Checks that the chain is non-empty and that `joints` and `lengths` agree.
+    pub fn validate(&self) -> Result<(), IkError> {
+        if self.joints.is_empty() {
+            return Err(IkError::EmptyChain);
+        }
+        if self.lengths.len() + 1 != self.joints.len() {
+            return Err(IkError::MismatchedLengths {
+                joints: self.joints.len(),
+                lengths: self.lengths.len(),
+            });
+        }
+        if let Some(index) = self.lengths.iter().position(|length| *length <= f32::EPSILON) {
+            return Err(IkError::ZeroLengthSegment(index));
+        }
+        if self.root >= self.joints.len() {
+            return Err(IkError::JointOutOfRange {
+                index: self.root,
+                len: self.joints.len(),
+            });
+        }
+        Ok(())
+    }
 
-            // Cross again to get a second perpendicular vector properly aligned
-            let perp_vector2 = ab_vector.cross(perp_vector).normalize();
+    pub fn recalculate_segments(&mut self) -> Result<(), IkError> {
+        self.validate()?;
+        let frame_delta_time = self.clock.tick(&mut self.prev_time);
 
-            // Create a quaternion from the perpendicular vector
-            let quat = Quat::from_mat3(&Mat3::from_cols(ab_vector, perp_vector, perp_vector2))
-                * Quat::from_rotation_z(90f32.to_radians());
+        self.angular_velocities.clear();
+        if let Some(frame_delta_time) = frame_delta_time.filter(|dt| !dt.is_zero()) {
+            for (angle, prev_angle) in self.angles.iter().zip(self.prev_angles.iter()) {
+                self.angular_velocities
+                    .push((angle - prev_angle) / frame_delta_time.as_secs_f32());
+            }
+        }
+        #[cfg(feature = "bevy_transform")]
+        {
+            self.segment_transforms.clear();
+            // Frames are carried along the chain rather than built against a fixed world axis,
+            // so they stay finite and don't flip when a segment points along that axis.
+            for (i, frame) in self.segment_frames().into_iter().enumerate() {
+                let a = self.joints[i + 1];
+                let b = self.joints[i];
 
-            self.segment_transforms.push(Transform {
-                translation: (a + b) / 2.0,
-                rotation: quat,
-                scale: Vec3::ONE,
-            });
+                self.segment_transforms.push(Transform {
+                    translation: (a + b) / 2.0,
+                    rotation: frame,
+                    scale: Vec3::ONE,
+                });
+            }
         }
-        assert_eq!(self.segment_transforms.len(), self.lengths.len());
+        Ok(())
     }
 
-    pub fn reset(&mut self) {
+    pub fn reset(&mut self) -> Result<(), IkError> {
         let initial_state = self
             .initial_state
             .clone()
-            .expect("initial state should not be blank");
+            .ok_or(IkError::MissingInitialState)?;
         *self = Self {
             initial_state: Some(initial_state.clone()),
             ..*initial_state
         };
-        self.recalculate_segments();
+        self.recalculate_segments()
     }
 
+    /// Places joint `i` along segment `i`, measured from joint `i + 1`.
+    fn place_down(&mut self, i: usize) {
+        let b = self.joints[i + 1];
+        let goal = self.segment_goal(i).map(|(goal, weight)| (-goal, weight));
+        let constraint = self.constraints.get(i + 1).copied().zip(
+            self.joints
+                .get(i + 2)
+                .map(|parent| (b - *parent).normalize()),
+        );
+        self.joints[i] = self.anchor_position(i).unwrap_or_else(|| {
+            place(b, self.joints[i], self.lengths[i], goal, constraint, true)
+        });
+    }
+
+    /// Places joint `i + 1` along segment `i`, measured from joint `i`.
+    fn place_up(&mut self, i: usize) {
+        let a = self.joints[i];
+        let goal = self.segment_goal(i);
+        let constraint = self.constraints.get(i).copied().zip(
+            i.checked_sub(1)
+                .map(|parent| (a - self.joints[parent]).normalize()),
+        );
+        self.joints[i + 1] = self.anchor_position(i + 1).unwrap_or_else(|| {
+            place(a, self.joints[i + 1], self.lengths[i], goal, constraint, false)
+        });
+    }
+
+    /// Reaches in from both ends towards the root. Every segment keeps its length, unless an
+    /// anchor point pins one of its joints, or both sides of a root partway along the chain
+    /// hold one.
     pub fn fwd_reach(&mut self) {
-        // 'FORWARD REACHING'
-        for i in (0..self.joints.len() - 1).rev() {
-            let a = self.joints[i];
-            let b = self.joints[i + 1];
-            let direction = (a - b).normalize();
-            self.joints[i] = b + direction * self.lengths[i];
+        // 'FORWARD REACHING', from both ends in towards the root
+        let root = self.root.min(self.joints.len().saturating_sub(1));
+        for i in (root..self.joints.len().saturating_sub(1)).rev() {
+            self.place_down(i);
+        }
+        let from_above = self.joints[root];
+        for i in 0..root {
+            self.place_up(i);
+        }
+        if root > 0 && root + 1 < self.joints.len() {
+            self.meet_at_root(root, from_above);
+        }
+    }
+
+    /// Both sides of a root partway along the chain place it in the forward pass. Moves it to
+    /// where one side put it, `from_above` or where the side below put it, and shifts the other
+    /// side along so the segments at the root keep their length. A side holding an anchor
+    /// point stays put, otherwise a side with targets wins over one without, and between two
+    /// alike the root goes halfway, as at a `Skeleton` sub-base.
+    fn meet_at_root(&mut self, root: usize, from_above: Vec3) {
+        let from_below = self.joints[root];
+        let pull = |joints: core::ops::Range<usize>| {
+            if joints.clone().any(|joint| self.anchor_position(joint).is_some()) {
+                2
+            } else if self.targets.iter().any(|target| joints.contains(&target.index)) {
+                1
+            } else {
+                0
+            }
+        };
+        let len = self.joints.len();
+        let meeting = match pull(0..root).cmp(&pull(root + 1..len)) {
+            Ordering::Greater => from_below,
+            Ordering::Less => from_above,
+            Ordering::Equal if pull(0..root) == 2 => return,
+            Ordering::Equal => from_above.lerp(from_below, 0.5),
+        };
+        for joint in &mut self.joints[..root] {
+            *joint += meeting - from_below;
+        }
+        for joint in &mut self.joints[root + 1..] {
+            *joint += meeting - from_above;
         }
+        self.joints[root] = meeting;
     }
 
     pub fn bwd_reach(&mut self) {
-        // 'BACKWARD REACHING'
-        for i in 0..self.joints.len() - 1 {
-            let a = self.joints[i];
-            let b = self.joints[i + 1];
-            let direction = (b - a).normalize();
-            self.joints[i + 1] = a + direction * self.lengths[i];
+        // 'BACKWARD REACHING', from the root out to both ends
+        let root = self.root.min(self.joints.len().saturating_sub(1));
+        for i in root..self.joints.len().saturating_sub(1) {
+            self.place_up(i);
+        }
+        for i in (0..root).rev() {
+            self.place_down(i);
         }
     }
 
     pub fn recalculate_angles(&mut self) {
-        std::mem::swap(&mut self.angles, &mut self.prev_angles);
+        core::mem::swap(&mut self.angles, &mut self.prev_angles);
         self.angles.clear();
-        self.angles.push(std::f32::consts::PI);
+        self.angles.push(PI);
         for i in 2..self.joints.len() {
             let a = self.joints[i - 2];
             let b = self.joints[i - 1];
             let c = self.joints[i];
             let angle = (a - b).angle_between(c - b);
-            self.angles.push(angle);
+            let constraint = self.constraints.get(i - 1).copied().unwrap_or_default();
+            self.angles.push(constraint.clamp_angle(angle));
+        }
+        self.angles.push(PI);
+    }
+
+    /// Runs up to `iterations` passes of `solver` towards `targets`, stopping once they are all
+    /// within `tolerance`. Returns the number of passes actually run.
+    pub(crate) fn iterate(&mut self, iterations: usize) -> usize {
+        let mut iterations_used = 0;
+        for _ in 0..iterations {
+            if self.targets_reached() {
+                break;
+            }
+            iterations_used += 1;
+            Arc::clone(&self.solver).step(self);
         }
-        self.angles.push(std::f32::consts::PI);
+        iterations_used
     }
 
-    pub fn solve(&mut self, iterations: usize, pose_discrepancy: PoseDiscrepancy, kinematics_mode: &mut KinematicsMode) {
+    pub fn solve(
+        &mut self,
+        iterations: usize,
+        pose_discrepancy: PoseDiscrepancy,
+        kinematics_mode: &mut KinematicsMode,
+    ) -> Result<SolveReport, IkError> {
+        self.validate()?;
+        let anchors = self.motion_heuristics.anchor_points.iter();
+        for index in self
+            .targets
+            .iter()
+            .map(|target| target.index)
+            .chain(anchors.map(|(index, _, _)| *index))
+        {
+            if index >= self.joints.len() {
+                return Err(IkError::JointOutOfRange {
+                    index,
+                    len: self.joints.len(),
+                });
+            }
+        }
+        let start = Stopwatch::start();
+        self.procedural_parenting();
+        let iterations_used;
+        let mut recovery = None;
+        let mut straightened = false;
         match pose_discrepancy {
             PoseDiscrepancy::WithinTolerance => {
                 *kinematics_mode = KinematicsMode::InverseKinematics;
                 self.recalculate_angles();
-                for _ in 0..iterations {
-                    for (index, pos) in self.targets.iter() {
-                        self.joints[*index] = *pos;
-                    }
-                    self.fwd_reach();
-                    if self.lock_ground {
-                        self.joints.first_mut().unwrap().clone_from(&Vec3::ZERO);
-                    }
-                    self.bwd_reach();
-                    for i in 0..self.joints.len() {
-                        dbg!(self.angles[i]);
+                // A straight chain is as close as a lone target out of reach can get, so
+                // start the solver from there when nothing keeps the chain from lying straight.
+                if let [target] = self.targets.as_slice() {
+                    if self.can_straighten_towards(target) {
+                        self.straighten_towards(target.index, target.position)?;
+                        straightened = true;
                     }
                 }
+                iterations_used = self.iterate(iterations);
             }
             PoseDiscrepancy::MildDivergence => {
-                *kinematics_mode = KinematicsMode::ForwardKinematics;
-                for i in 0..self.joints.len() {
-                    let residual_vec =
-                        self.fantasy_limb.as_ref().unwrap().joints[i] - self.joints[i];
-                    let infinitesimal_approximation = residual_vec / 2.;
-                    let r_hat = residual_vec.normalize();
-                    let r_hat_div_angle = r_hat / self.angles[i];
-                    dbg!(r_hat_div_angle);
-                    // let d_r =
-                }
+                return Err(IkError::UnsupportedMode("MildDivergence"));
             }
             PoseDiscrepancy::SevereDivergence => {
-                todo!(); // More intensive adjustments or alternative strategies
+                *kinematics_mode = KinematicsMode::InverseKinematics;
+                self.recalculate_angles();
+                let (used, taken) = self.recover(iterations)?;
+                iterations_used = used;
+                recovery = Some(taken);
             }
             PoseDiscrepancy::EnvironmentalCompensation => {
-                todo!(); // Special handling for environmental factors, introducing intentional divergences
+                *kinematics_mode = KinematicsMode::InverseKinematics;
+                self.recalculate_angles();
+                iterations_used = self.iterate_avoiding(iterations);
             }
         }
-        self.recalculate_segments();
+        self.recalculate_segments()?;
+        let mut report = self.report(iterations_used, start.elapsed());
+        report.recovery = recovery;
+        report.straightened = straightened;
+        Ok(report)
+    }
+}
+
+/// Where a joint laid out `length` away from `from` ends up, starting from the direction of
+/// its current position `to`: turned towards `goal` by its weight, then pulled back inside a
+/// joint constraint at `from`, measured against the reference direction of the segment on
+/// the other side of `from`. `reversed` is set when laying joints out towards the root.
+/// `FabrikChain` and `Skeleton` place every joint with this.
+pub(crate) fn place(
+    from: Vec3,
+    to: Vec3,
+    length: f32,
+    goal: Option<(Vec3, f32)>,
+    constraint: Option<(JointConstraint, Vec3)>,
+    reversed: bool,
+) -> Vec3 {
+    let mut direction = (to - from).normalize();
+    if let Some((goal, weight)) = goal {
+        direction = targets::steer(direction, goal, weight);
+    }
+    if let Some((constraint, reference)) = constraint {
+        direction = constraint.apply(reference, direction, reversed);
     }
+    from + direction * length
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[cfg(not(feature = "std"))]
+    extern crate std;
+    #[cfg(not(feature = "std"))]
+    use std::dbg;
+
+    /// `joints` joints a unit apart, running from the origin along `axis`.
+    fn straight(joints: usize, axis: Vec3) -> Vec<Vec3> {
+        (0..joints).map(|i| axis * i as f32).collect()
+    }
+
+    fn straight_chain(joints: usize, axis: Vec3) -> FabrikChain {
+        FabrikChain::new(straight(joints, axis), MotionHeuristics::default()).unwrap()
+    }
 
     #[test]
     fn test_initialize_lengths() {
@@ -249,30 +516,1319 @@ mod tests {
             Vec3::new(2.0, 0.0, 0.0),
         ];
         let motion_heuristics = MotionHeuristics::new(Vec::new(), Vec::new());
-        let chain = FabrikChain::new(joints, motion_heuristics);
+        let chain = FabrikChain::new(joints, motion_heuristics).unwrap();
         dbg!(&chain);
 
         assert_eq!(chain.lengths, vec![1.0, 1.0]);
     }
 
-    // #[test]
-    // fn test_fabrik_solve() {
-    //     let joints = vec![
-    //         Vec3::new(0.0, 0.0, 0.0),
-    //         Vec3::new(1.0, 0.0, 0.0),
-    //         Vec3::new(2.0, 0.0, 0.0),
-    //     ];
-    //     let motion_heuristics = MotionHeuristics {
-    //         anchor_points: Vec::new(),
-    //         parent_ranking: Vec::new(),
-    //     };
+    #[test]
+    fn test_hinge_clamp_angle_wraps() {
+        // Bends from 2.5 round past π to 4.0, i.e. between 2.28 and π either way.
+        let hinge = JointConstraint::Hinge {
+            axis: Vec3::Z,
+            min: 2.5,
+            max: 4.0,
+        };
+        let least_bent = PI - (2.0 * PI - 4.0);
+        assert!((hinge.clamp_angle(least_bent) - least_bent).abs() < 1e-6);
+        assert_eq!(hinge.clamp_angle(0.0), 0.0);
+        assert!((hinge.clamp_angle(PI - 1.0) - least_bent).abs() < 1e-6);
 
-    //     let mut chain = FabrikChain::new(joints, motion_heuristics);
-    //     dbg!(&chain);
-    //     let target = Vec3::new(3.0, 0.0, 0.0);
+        // A range across 0 allows a straight joint, one that doesn't holds it bent.
+        let knee = JointConstraint::Hinge {
+            axis: Vec3::Z,
+            min: 0.5,
+            max: 2.0,
+        };
+        assert_eq!(knee.clamp_angle(PI), PI - 0.5);
+        assert_eq!(knee.clamp_angle(0.5), PI - 2.0);
+        let elbow = JointConstraint::Hinge {
+            axis: Vec3::Z,
+            min: -1.0,
+            max: 0.5,
+        };
+        assert_eq!(elbow.clamp_angle(PI), PI);
+        assert_eq!(elbow.clamp_angle(0.5), PI - 1.0);
+    }
 
-    //     chain.solve( 10,PoseDiscrepancy::default() );
+    #[test]
+    fn test_hinge_blocks_backwards_bend() {
+        let mut chain = straight_chain(3, Vec3::X);
+        chain.set_constraint(
+            1,
+            JointConstraint::Hinge {
+                axis: Vec3::Z,
+                min: 0.0,
+                max: PI / 2.0,
+            },
+        )
+        .unwrap();
+        // Bending towards -Y is a negative bend about +Z, which the hinge forbids.
+        chain.targets.push(Target::new(2, Vec3::new(1.0, -1.0, 0.0)));
+        chain.solve(20, PoseDiscrepancy::default(), &mut KinematicsMode::default())
+            .unwrap();
+
+        let elbow = chain.joints[1] - chain.joints[0];
+        let forearm = chain.joints[2] - chain.joints[1];
+        assert!(Vec3::Z.dot(elbow.cross(forearm)) >= -1e-4);
+    }
+
+    #[test]
+    fn test_solve_report() {
+        let mut chain = straight_chain(3, Vec3::X);
+        chain.targets.push(Target::new(2, Vec3::new(1.0, 1.0, 0.0)));
+        let report = chain.solve(100, PoseDiscrepancy::default(), &mut KinematicsMode::default())
+            .unwrap();
+        assert!(report.reached);
+        assert!(report.iterations < 100);
+        assert!(report.max_residual() <= chain.tolerance);
+
+        chain.targets = vec![Target::new(2, Vec3::new(5.0, 0.0, 0.0))];
+        let report = chain.solve(10, PoseDiscrepancy::default(), &mut KinematicsMode::default())
+            .unwrap();
+        assert!(!report.reached);
+        assert_eq!(report.iterations, 10);
+    }
+
+    #[test]
+    fn test_unreachable_target_straightens_chain() {
+        let mut chain = straight_chain(3, Vec3::X);
+        let target = Vec3::new(0.0, 3.0, 4.0);
+        assert_eq!(chain.is_reachable(2, target), Ok(false));
+        assert_eq!(chain.is_reachable(1, Vec3::new(0.0, 0.6, 0.8)), Ok(true));
+        assert_eq!(
+            chain.project_to_reachable(2, target),
+            Ok(Vec3::new(0.0, 1.2, 1.6))
+        );
+        let out_of_range = IkError::JointOutOfRange { index: 3, len: 3 };
+        assert_eq!(chain.reach(3), Err(out_of_range.clone()));
+        assert_eq!(chain.is_reachable(3, target), Err(out_of_range.clone()));
+        assert_eq!(chain.project_to_reachable(3, target), Err(out_of_range.clone()));
+        assert_eq!(chain.straighten_towards(3, target), Err(out_of_range));
+
+        chain.targets.push(Target::new(2, target));
+        let report = chain
+            .solve(10, PoseDiscrepancy::default(), &mut KinematicsMode::default())
+            .unwrap();
+        assert!(report.straightened);
+        assert_eq!(report.unreachable, vec![2]);
+        assert!(chain.joints[2].distance(Vec3::new(0.0, 1.2, 1.6)) < 1e-5);
+
+        // A knee that can't straighten, or a target partway along the chain, leaves it to the
+        // solver.
+        chain.reset().unwrap();
+        chain.targets.push(Target::new(2, target));
+        let knee = JointConstraint::Hinge {
+            axis: Vec3::Z,
+            min: 0.5,
+            max: 2.0,
+        };
+        chain.set_constraint(1, knee).unwrap();
+        assert!(!chain.can_straighten_towards(&chain.targets[0]));
+        let report = chain
+            .solve(10, PoseDiscrepancy::default(), &mut KinematicsMode::default())
+            .unwrap();
+        assert!(!report.straightened);
+        chain.set_constraint(1, JointConstraint::Unconstrained).unwrap();
+        assert!(!chain.can_straighten_towards(&Target::new(1, target)));
+    }
+
+    #[test]
+    fn test_errors_instead_of_panics() {
+        let result = FabrikChain::new(Vec::new(), MotionHeuristics::default());
+        assert_eq!(result.unwrap_err(), IkError::EmptyChain);
+
+        let joints = vec![Vec3::ZERO, Vec3::X, Vec3::X];
+        let result = FabrikChain::new(joints, MotionHeuristics::default());
+        assert_eq!(result.unwrap_err(), IkError::ZeroLengthSegment(1));
+
+        let mut chain = straight_chain(2, Vec3::X);
+        chain.targets.push(Target::new(2, Vec3::Y));
+        let result = chain.solve(10, PoseDiscrepancy::default(), &mut KinematicsMode::default());
+        assert_eq!(result.unwrap_err(), IkError::JointOutOfRange { index: 2, len: 2 });
+
+        chain.fantasy_limb = None;
+        chain.targets.clear();
+        let result = chain.solve(
+            10,
+            PoseDiscrepancy::SevereDivergence,
+            &mut KinematicsMode::default(),
+        );
+        assert_eq!(result.unwrap_err(), IkError::MissingFantasyLimb);
+
+        let result = chain.solve(
+            10,
+            PoseDiscrepancy::MildDivergence,
+            &mut KinematicsMode::default(),
+        );
+        assert_eq!(
+            result.unwrap_err(),
+            IkError::UnsupportedMode("MildDivergence")
+        );
+    }
+
+    #[test]
+    fn test_single_joint_chain_with_oriented_target() {
+        let solvers: [Arc<dyn Solver>; 3] = [
+            Arc::new(Fabrik),
+            Arc::new(Ccd),
+            Arc::new(DampedLeastSquares::default()),
+        ];
+        for solver in solvers {
+            let mut chain =
+                FabrikChain::new(vec![Vec3::ZERO], MotionHeuristics::default()).unwrap();
+            chain.solver = solver;
+            chain
+                .targets
+                .push(Target::new(0, Vec3::X).with_orientation(Quat::from_rotation_z(1.0), 1.0));
+            let report = chain
+                .solve(10, PoseDiscrepancy::default(), &mut KinematicsMode::default())
+                .unwrap();
+            // There is no segment to orient, and the pinned joint can't move.
+            assert!(report.orientation_residuals.is_empty());
+            assert_eq!(report.residuals, vec![(0, 1.0)]);
+            assert_eq!(chain.joint_orientation(0), None);
+        }
+    }
+
+    #[test]
+    fn test_severe_divergence_recovery() {
+        let mut chain = straight_chain(3, Vec3::X);
+        // The real limb got knocked far away from where the fantasy limb thinks it is.
+        chain.joints = vec![
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.0, 0.0, 2.0),
+        ];
+        let knocked = chain.joints.clone();
+        chain.targets.push(Target::new(2, Vec3::new(1.0, 1.0, 0.0)));
+        let report = chain
+            .solve(10, PoseDiscrepancy::SevereDivergence, &mut KinematicsMode::default())
+            .unwrap();
+
+        let recovery = report.recovery.unwrap();
+        assert_eq!(recovery.strategy, RecoveryStrategy::ReseededFromFantasy);
+        assert!(recovery.divergence > 1.0);
+        assert!(recovery.rate_limited);
+        for (joint, knocked) in chain.joints.iter().zip(&knocked) {
+            assert!(joint.distance(*knocked) <= chain.recovery.max_step + 1e-5);
+        }
+        for i in 0..chain.lengths.len() {
+            let length = chain.joints[i].distance(chain.joints[i + 1]);
+            assert!((length - chain.lengths[i]).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_environmental_compensation_avoids_obstacles() {
+        let mut chain = straight_chain(4, Vec3::X);
+        chain.environment = Environment::new(
+            vec![Obstacle::Sphere {
+                center: Vec3::new(1.5, 0.5, 0.0),
+                radius: 0.4,
+            }],
+            0.05,
+        );
+        chain.targets.push(Target::new(3, Vec3::new(1.5, 2.0, 0.0)));
+
+        let mut unaware = chain.clone();
+        let report = unaware
+            .solve(50, PoseDiscrepancy::default(), &mut KinematicsMode::default())
+            .unwrap();
+        assert!(report.contacts > 0);
+
+        let report = chain
+            .solve(
+                50,
+                PoseDiscrepancy::EnvironmentalCompensation,
+                &mut KinematicsMode::default(),
+            )
+            .unwrap();
+
+        assert_eq!(report.contacts, 0);
+        assert!(report.reached);
+        let fantasy_limb = chain.fantasy_limb.as_ref().unwrap();
+        assert_eq!(fantasy_limb.joints[3], Vec3::new(3.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_avoiding_obstacles_keeps_segment_lengths() {
+        let joints = (0..5).map(|i| Vec3::new(i as f32, 0.0, 0.0)).collect();
+        let mut chain = FabrikChain::new(joints, MotionHeuristics::default()).unwrap();
+        chain.root = 2;
+        chain.ground = chain.joints[2];
+        // Each obstacle only cuts across one segment, on either side of the root, and the
+        // targets keep pulling the chain straight through them.
+        chain.environment = Environment::new(
+            vec![
+                Obstacle::Sphere {
+                    center: Vec3::new(2.5, 0.3, 0.0),
+                    radius: 0.4,
+                },
+                Obstacle::Sphere {
+                    center: Vec3::new(0.5, -0.3, 0.0),
+                    radius: 0.4,
+                },
+            ],
+            0.05,
+        );
+        chain.targets = vec![
+            Target::new(4, Vec3::new(4.0, 0.0, 0.0)),
+            Target::new(0, Vec3::new(0.0, 0.0, 0.0)),
+        ];
+        chain.iterate_avoiding(20);
+
+        assert_eq!(chain.joints[2], Vec3::new(2.0, 0.0, 0.0));
+        assert_eq!(chain.contacts(), 0);
+        for i in 0..chain.lengths.len() {
+            let length = chain.joints[i].distance(chain.joints[i + 1]);
+            assert!((length - chain.lengths[i]).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_skeleton_single_branch_matches_chain() {
+        let joints = straight(4, Vec3::X);
+        let anchors = vec![(1, Vec3::new(0.8, 0.6, 0.0), Quat::IDENTITY)];
+        let mut chain =
+            FabrikChain::new(joints, MotionHeuristics::new(anchors, Vec::new())).unwrap();
+        chain
+            .set_constraint(
+                2,
+                JointConstraint::Hinge {
+                    axis: Vec3::Z,
+                    min: -PI / 2.0,
+                    max: 0.0,
+                },
+            )
+            .unwrap();
+        let down = Quat::from_rotation_arc(BONE_AXIS, Vec3::NEG_Y);
+        chain.targets.push(
+            Target::new(3, Vec3::new(1.0, 2.0, 0.5))
+                .with_position_weight(0.8)
+                .with_orientation(down, 0.5),
+        );
+        let mut skeleton = Skeleton::from_chain(&chain).unwrap();
+        chain
+            .solve(5, PoseDiscrepancy::default(), &mut KinematicsMode::default())
+            .unwrap();
+        skeleton.solve(5).unwrap();
+
+        for (a, b) in chain.joints.iter().zip(skeleton.joints.iter()) {
+            assert!(a.distance(*b) < 1e-5);
+        }
+
+        // The ground belongs to the root, which a skeleton can only have at joint 0.
+        chain.root = 2;
+        chain.ground = chain.joints[2];
+        let result = Skeleton::from_chain(&chain);
+        assert_eq!(result.unwrap_err(), IkError::UnsupportedRoot(2));
+    }
+
+    #[test]
+    fn test_orientation_target() {
+        let mut chain = straight_chain(4, Vec3::X);
+        // Come down onto the target from above.
+        let from_above = Quat::from_rotation_arc(BONE_AXIS, Vec3::NEG_Y);
+        chain
+            .targets
+            .push(Target::new(3, Vec3::new(1.0, 0.5, 0.0)).with_orientation(from_above, 1.0));
+        let report = chain
+            .solve(100, PoseDiscrepancy::default(), &mut KinematicsMode::default())
+            .unwrap();
+
+        assert!(report.reached);
+        let last_segment = (chain.joints[3] - chain.joints[2]).normalize();
+        assert!(last_segment.distance(Vec3::NEG_Y) < 1e-2);
+    }
+
+    #[test]
+    fn test_anchor_rotation_is_honored() {
+        let joints = straight(4, Vec3::X);
+        // The root is planted pointing straight up.
+        let anchors = vec![(0, Vec3::ZERO, Quat::IDENTITY)];
+        let mut chain = FabrikChain::new(joints, MotionHeuristics::new(anchors, Vec::new())).unwrap();
+        chain.targets.push(Target::new(3, Vec3::new(1.0, 2.0, 0.0)));
+        chain
+            .solve(100, PoseDiscrepancy::default(), &mut KinematicsMode::default())
+            .unwrap();
+
+        assert_eq!(chain.joints[0], Vec3::ZERO);
+        assert!(chain.joints[1].distance(Vec3::Y) < 1e-4);
+    }
+
+    #[test]
+    fn test_forward_kinematics_round_trip() {
+        let mut chain = straight_chain(4, Vec3::X);
+        chain.targets.push(Target::new(3, Vec3::new(0.5, 1.5, 1.0)));
+        chain
+            .solve(50, PoseDiscrepancy::default(), &mut KinematicsMode::default())
+            .unwrap();
+        let solved = chain.joints.clone();
+        let rotations = chain.joint_rotations();
+
+        chain.reset().unwrap();
+        chain.set_joint_rotations(&rotations).unwrap();
+        for (a, b) in chain.joints.iter().zip(solved.iter()) {
+            assert!(a.distance(*b) < 1e-5);
+        }
+
+        // A quarter turn about Z at the root swings the straight chain from +Y onto -X.
+        let angles = vec![(Vec3::Z, PI / 2.0), (Vec3::Z, 0.0), (Vec3::Z, 0.0)];
+        chain.set_joint_angles(&angles).unwrap();
+        assert!(chain.joints[3].distance(Vec3::new(-3.0, 0.0, 0.0)) < 1e-5);
+
+        // Re-rooted at a planted joint, the pose is laid out around it instead.
+        chain.root = 2;
+        chain.ground = Vec3::new(1.0, 1.0, 0.0);
+        chain.set_joint_rotations(&rotations).unwrap();
+        assert_eq!(chain.joints[2], Vec3::new(1.0, 1.0, 0.0));
+        let offset = chain.joints[2] - solved[2];
+        for (a, b) in chain.joints.iter().zip(solved.iter()) {
+            assert!(a.distance(*b + offset) < 1e-5);
+        }
+
+        let result = chain.set_joint_rotations(&rotations[1..]);
+        assert_eq!(
+            result.unwrap_err(),
+            IkError::MismatchedRotations {
+                segments: 3,
+                rotations: 2
+            }
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "bevy_transform")]
+    fn test_segment_frames_through_vertical() {
+        let tilted = |x: f32| {
+            let joints = vec![
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(x, 1.0, 0.0),
+                Vec3::new(x, 2.0, 0.0),
+            ];
+            FabrikChain::new(joints, MotionHeuristics::default()).unwrap()
+        };
+        let left = tilted(-1e-3).segment_transforms;
+        let vertical = tilted(0.0).segment_transforms;
+        let right = tilted(1e-3).segment_transforms;
+        for transforms in [&left, &vertical, &right] {
+            for transform in transforms.iter() {
+                assert!(transform.rotation.is_finite());
+                assert!((transform.rotation * BONE_AXIS).distance(Vec3::Y) < 1e-2);
+            }
+        }
+        for i in 0..vertical.len() {
+            assert!(left[i].rotation.angle_between(right[i].rotation) < 1e-2);
+        }
+
+        // Hanging straight down is only a problem with the default up vector.
+        let mut chain = straight_chain(3, Vec3::NEG_Y);
+        chain.up = Vec3::NEG_Y;
+        chain.recalculate_segments().unwrap();
+        for transform in chain.segment_transforms.iter() {
+            assert!(transform.rotation.is_finite());
+            assert!((transform.rotation * BONE_AXIS).distance(Vec3::NEG_Y) < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_signed_joint_angles() {
+        let bent = |x: f32| {
+            let joints = vec![
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                Vec3::new(x, 1.0, 0.0).normalize() + Vec3::Y,
+            ];
+            FabrikChain::new(joints, MotionHeuristics::default()).unwrap()
+        };
+        let right = bent(1.0).joint_angles();
+        let left = bent(-1.0).joint_angles();
+        assert_eq!(right[0], JointAngles::default());
+        // Mirrored bends give mirrored angles rather than the same unsigned one.
+        assert!((right[1].yaw + PI / 4.0).abs() < 1e-5);
+        assert!((left[1].yaw - PI / 4.0).abs() < 1e-5);
+        assert!(right[1].pitch.abs() < 1e-5 && right[1].roll.abs() < 1e-5);
+
+        let twisted = JointAngles {
+            pitch: 0.3,
+            yaw: -0.7,
+            roll: 1.2,
+        };
+        let round_trip = JointAngles::from_rotation(twisted.to_rotation());
+        assert!((round_trip.pitch - twisted.pitch).abs() < 1e-5);
+        assert!((round_trip.yaw - twisted.yaw).abs() < 1e-5);
+        assert!((round_trip.roll - twisted.roll).abs() < 1e-5);
+
+        let mut chain = bent(1.0);
+        let solved = chain.joints.clone();
+        chain.set_joint_space(&right).unwrap();
+        for (a, b) in chain.joints.iter().zip(solved.iter()) {
+            assert!(a.distance(*b) < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_fixed_step_clock_is_deterministic() {
+        let mut chain = straight_chain(3, Vec3::X);
+        chain.clock = Clock::FixedStep(Duration::from_millis(10));
+        let mut other = chain.clone();
+        for limb in [&mut chain, &mut other] {
+            for target in [Vec3::new(1.0, 1.0, 0.0), Vec3::new(0.0, 2.0, 0.0)] {
+                limb.targets = vec![Target::new(2, target)];
+                limb.solve(10, PoseDiscrepancy::default(), &mut KinematicsMode::default())
+                    .unwrap();
+            }
+        }
+        assert!(!chain.angular_velocities.is_empty());
+        assert_eq!(chain.angular_velocities, other.angular_velocities);
+        let expected = (chain.angles[1] - chain.prev_angles[1]) / 0.01;
+        assert_eq!(chain.angular_velocities[1], expected);
+
+        // Steps shorter than a microsecond still give finite velocities.
+        chain.clock = Clock::FixedStep(Duration::from_nanos(100));
+        chain.targets = vec![Target::new(2, Vec3::new(1.0, 1.0, 0.0))];
+        chain.solve(10, PoseDiscrepancy::default(), &mut KinematicsMode::default())
+            .unwrap();
+        assert!(chain.angular_velocities.iter().all(|velocity| velocity.is_finite()));
+
+        // The monotonic clock has nothing to measure the very first frame against.
+        let chain = straight_chain(2, Vec3::X);
+        assert!(chain.angular_velocities.is_empty());
+    }
+
+    #[test]
+    fn test_procedural_parenting_reroots_at_planted_joint() {
+        let mut chain = straight_chain(5, Vec3::X);
+        // Plant the far end and rank it above the old root.
+        let planted = Vec3::new(4.0, 0.0, 0.0);
+        chain.motion_heuristics.anchor_points = vec![(4, planted, Quat::IDENTITY)];
+        chain.motion_heuristics.parent_ranking = vec![(0, 0, 0), (4, 1, 0)];
+
+        let before = chain.joints.clone();
+        assert!(chain.procedural_parenting());
+        assert_eq!(chain.root, 4);
+        assert_eq!(chain.joints, before);
+
+        chain.targets.push(Target::new(0, Vec3::new(2.0, 1.0, 0.0)));
+        let report = chain
+            .solve(50, PoseDiscrepancy::default(), &mut KinematicsMode::default())
+            .unwrap();
+        assert!(report.reached);
+        assert_eq!(chain.joints[4], planted);
+    }
 
-    //     assert!((*chain.joints.last().unwrap() - target).length() < 0.01);
-    // }
+    #[test]
+    fn test_skeleton_reaches_two_end_effectors() {
+        // A torso with a two-segment arm on each side.
+        let joints = vec![
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(-1.0, 1.0, 0.0),
+            Vec3::new(-2.0, 1.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::new(2.0, 1.0, 0.0),
+        ];
+        let parents = vec![None, Some(0), Some(1), Some(2), Some(1), Some(4)];
+        let mut skeleton = Skeleton::new(joints, parents).unwrap();
+        assert_eq!(skeleton.end_effectors().collect::<Vec<_>>(), vec![3, 5]);
+        assert_eq!(skeleton.branch(5), vec![0, 1, 4, 5]);
+
+        skeleton.targets = vec![
+            Target::new(3, Vec3::new(-1.5, 2.0, 0.5)),
+            Target::new(5, Vec3::new(1.5, 2.0, 0.5)),
+        ];
+        let report = skeleton.solve(100).unwrap();
+        assert!(report.reached);
+        assert_eq!(skeleton.joints[0], Vec3::ZERO);
+        for joint in 1..skeleton.joints.len() {
+            let parent = skeleton.parents[joint].unwrap();
+            let length = skeleton.joints[joint].distance(skeleton.joints[parent]);
+            assert!((length - skeleton.lengths[joint]).abs() < 1e-4);
+        }
+
+        let result = Skeleton::new(vec![Vec3::ZERO, Vec3::X], vec![None, None]);
+        assert_eq!(result.unwrap_err(), IkError::InvalidParent(1));
+    }
+
+    #[test]
+    fn test_rig_round_trip() {
+        let mut chain = straight_chain(3, Vec3::X);
+        chain
+            .set_constraint(1, JointConstraint::BallSocket { max_swing: 1.0 })
+            .unwrap();
+        chain.tolerance = 1e-2;
+        chain.clock = Clock::FixedStep(Duration::from_millis(10));
+        chain.solver = Arc::new(Ccd);
+        let rig = chain.to_rig();
+        let mut loaded = FabrikChain::from_rig(rig.clone()).unwrap();
+        assert_eq!(loaded.to_rig(), rig);
+        assert_eq!(loaded.joints, chain.joints);
+        assert_eq!(loaded.solver.kind(), Some(SolverKind::Ccd));
+
+        let dls = DampedLeastSquares::new(0.3);
+        let rig = Rig {
+            solver: SolverSettings {
+                solver: Some(SolverKind::DampedLeastSquares(dls)),
+                ..rig.solver.clone()
+            },
+            ..rig
+        };
+        let reloaded = FabrikChain::from_rig(rig.clone()).unwrap();
+        assert_eq!(reloaded.solver.kind(), Some(SolverKind::DampedLeastSquares(dls)));
+
+        // Lengths from the rig win over the spacing of its joints.
+        let stretched = Rig {
+            lengths: Some(vec![2.0, 2.0]),
+            ..rig.clone()
+        };
+        let stretched = FabrikChain::from_rig(stretched).unwrap();
+        assert!((stretched.joints[2] - Vec3::new(4.0, 0.0, 0.0)).length() < 1e-5);
+
+        let unconstrained = Rig {
+            constraints: vec![JointConstraint::Unconstrained; 2],
+            ..rig.clone()
+        };
+        assert_eq!(
+            FabrikChain::from_rig(unconstrained).unwrap_err(),
+            IkError::MismatchedConstraints {
+                joints: 3,
+                constraints: 2,
+            }
+        );
+
+        let future = Rig {
+            version: RIG_VERSION + 1,
+            ..rig
+        };
+        assert_eq!(
+            FabrikChain::from_rig(future).unwrap_err(),
+            IkError::UnsupportedRigVersion {
+                found: RIG_VERSION + 1,
+                supported: RIG_VERSION,
+            }
+        );
+
+        loaded.joints[2] = Vec3::new(1.0, 1.0, 0.0);
+        loaded.reset().unwrap();
+        assert_eq!(loaded.joints, chain.joints);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_rig_serde_formats() {
+        let source = r#"(
+            version: 1,
+            joints: [(0.0, 0.0, 0.0), (0.0, 1.0, 0.0), (0.0, 2.0, 0.0)],
+            constraints: [
+                Unconstrained,
+                Hinge(axis: (0.0, 0.0, 1.0), min: -1.0, max: 1.0),
+                Unconstrained,
+            ],
+            solver: (lock_ground: false, solver: Some(DampedLeastSquares((damping: 0.2)))),
+        )"#;
+        let rig = Rig::from_ron_str(source).unwrap();
+        let chain = FabrikChain::from_rig(rig).unwrap();
+        assert!(!chain.lock_ground);
+        let dls = SolverKind::DampedLeastSquares(DampedLeastSquares::new(0.2));
+        assert_eq!(chain.solver.kind(), Some(dls));
+        assert_eq!(chain.tolerance, SolverSettings::default().tolerance);
+        assert_eq!(chain.lengths, vec![1.0, 1.0]);
+
+        let rig = chain.to_rig();
+        assert_eq!(Rig::from_ron_str(&rig.to_ron_string().unwrap()).unwrap(), rig);
+        assert_eq!(Rig::from_json_str(&rig.to_json_string().unwrap()).unwrap(), rig);
+        assert!(matches!(Rig::from_json_str("{}"), Err(IkError::Rig(_))));
+    }
+
+    #[cfg(feature = "urdf")]
+    #[test]
+    fn test_urdf_import() {
+        let urdf = Urdf::parse(include_str!("../fixtures/planar_arm.urdf")).unwrap();
+        assert_eq!(urdf.name, "planar_arm");
+        assert_eq!(urdf.joints.len(), 4);
+        let chain = FabrikChain::from_urdf(&urdf, "base_link", "tool").unwrap();
+        let expected = [
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 0.5),
+            Vec3::new(0.0, 0.0, 1.5),
+            Vec3::new(1.0, 0.0, 1.5),
+            Vec3::new(1.25, 0.0, 1.5),
+        ];
+        assert_eq!(chain.joints.len(), expected.len());
+        for (joint, expected) in chain.joints.iter().zip(expected) {
+            assert!((*joint - expected).length() < 1e-5);
+        }
+        assert_eq!(chain.up, Vec3::Z);
+        let JointConstraint::Hinge { axis, min, max } = chain.constraints[2] else {
+            panic!("elbow should be a hinge");
+        };
+        assert!((axis - Vec3::Y).length() < 1e-5);
+        assert!((min - PI / 2.0).abs() < 1e-4);
+        assert!((max - (PI / 2.0 + 2.0)).abs() < 1e-4);
+        // The wrist rolls about the segment it carries, so it cannot bend it.
+        let JointConstraint::Hinge { min, max, .. } = chain.constraints[3] else {
+            panic!("wrist should be locked");
+        };
+        assert!(min.abs() < 1e-4 && max.abs() < 1e-4);
+
+        let slider = Urdf::parse(include_str!("../fixtures/slider_arm.urdf")).unwrap();
+        let chain = FabrikChain::from_urdf(&slider, "base_link", "tool").unwrap();
+        assert_eq!(chain.lengths.len(), 3);
+        assert!(matches!(chain.constraints[1], JointConstraint::Hinge { .. }));
+        assert_eq!(chain.constraints[2], JointConstraint::Unconstrained);
+
+        assert!(matches!(
+            FabrikChain::from_urdf(&urdf, "tool", "base_link"),
+            Err(IkError::Urdf(_))
+        ));
+        assert!(matches!(Urdf::parse("<robot><joint/></robot>"), Err(IkError::Urdf(_))));
+    }
+
+    #[cfg(feature = "urdf")]
+    #[test]
+    fn test_urdf_hinge_ranges_wrap() {
+        let urdf = Urdf::parse(include_str!("../fixtures/bent_arm.urdf")).unwrap();
+        let chain = FabrikChain::from_urdf(&urdf, "base_link", "tool").unwrap();
+        let elbow = chain.constraints[2];
+        let JointConstraint::Hinge { axis, min, max } = elbow else {
+            panic!("elbow should be a hinge");
+        };
+        assert!((axis - Vec3::Y).length() < 1e-5);
+        assert!((min - (0.5 - PI)).abs() < 1e-4 && (max - (0.5 + PI)).abs() < 1e-4);
+        // Bent 0.5 at rest, the continuous elbow still turns all the way round.
+        for bend in [-3.0, -2.9, 0.0, 0.5, 3.0] {
+            let direction = Quat::from_axis_angle(Vec3::Y, bend) * Vec3::Z;
+            assert!(elbow.apply(Vec3::Z, direction, false).distance(direction) < 1e-5);
+            assert!(elbow.apply(Vec3::Z, direction, true).distance(direction) < 1e-5);
+        }
+
+        // The planar arm's elbow rests at a right angle and bends 2 further, past ±π.
+        let urdf = Urdf::parse(include_str!("../fixtures/planar_arm.urdf")).unwrap();
+        let elbow = FabrikChain::from_urdf(&urdf, "base_link", "tool")
+            .unwrap()
+            .constraints[2];
+        let bent = |bend: f32| Quat::from_axis_angle(Vec3::Y, bend) * Vec3::Z;
+        assert!(elbow.apply(Vec3::Z, bent(3.5), false).distance(bent(3.5)) < 1e-5);
+        assert!(elbow.apply(Vec3::Z, bent(0.0), false).distance(bent(PI / 2.0)) < 1e-5);
+        assert!(elbow.apply(Vec3::Z, bent(-1.0), false).distance(bent(PI / 2.0 + 2.0)) < 1e-5);
+    }
+
+    #[cfg(feature = "gltf")]
+    #[test]
+    fn test_gltf_round_trip() {
+        let mut skeleton =
+            GltfSkeleton::from_slice(include_bytes!("../fixtures/arm.gltf")).unwrap();
+        let mut chain = FabrikChain::from_gltf(&skeleton, "shoulder", "hand").unwrap();
+        let expected = [
+            Vec3::new(0.0, 0.5, 0.0),
+            Vec3::new(0.0, 1.5, 0.0),
+            Vec3::new(0.0, 2.5, 0.0),
+            Vec3::new(-0.5, 2.5, 0.0),
+        ];
+        for (joint, expected) in chain.joints.iter().zip(expected) {
+            assert!((*joint - expected).length() < 1e-5);
+        }
+        assert_eq!(chain.ground, expected[0]);
+
+        chain.targets.push(Target::new(3, Vec3::new(1.5, 1.5, 0.5)));
+        let report = chain
+            .solve(50, PoseDiscrepancy::default(), &mut KinematicsMode::default())
+            .unwrap();
+        assert!(report.reached);
+        skeleton.write_pose("shoulder", "hand", &chain).unwrap();
+
+        let reloaded = GltfSkeleton::from_slice(&skeleton.to_vec().unwrap()).unwrap();
+        let path = reloaded.path("shoulder", "hand").unwrap();
+        for (node, joint) in path.into_iter().zip(&chain.joints) {
+            let position = reloaded.world_transform(node).translation;
+            assert!((position - *joint).length() < 1e-4);
+        }
+        assert!(matches!(
+            FabrikChain::from_gltf(&skeleton, "armature", "hand"),
+            Err(IkError::Gltf(_))
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "bevy_transform")]
+    fn test_bvh_round_trip() {
+        let mut chain = straight_chain(4, Vec3::Y);
+        let names = ["hips", "spine", "neck", "head"];
+        let mut clip = Bvh::from_chain(&chain, &names, Duration::from_millis(40)).unwrap();
+        clip.push_pose(&chain).unwrap();
+        chain.targets.push(Target::new(3, Vec3::new(1.5, 2.0, 0.5)));
+        chain
+            .solve(50, PoseDiscrepancy::default(), &mut KinematicsMode::default())
+            .unwrap();
+        clip.push_pose(&chain).unwrap();
+
+        let clip = Bvh::parse(&clip.to_string()).unwrap();
+        assert_eq!(clip.frames.len(), 2);
+        assert_eq!(clip.frame_time, Duration::from_millis(40));
+        let world = clip.world_transforms(1).unwrap();
+        for (transform, joint) in world.iter().zip(&chain.joints) {
+            assert!((transform.translation - *joint).length() < 1e-4);
+        }
+
+        let targets = clip.targets(1, &[("head", 3), ("spine", 1)]).unwrap();
+        assert_eq!(targets[0].index, 3);
+        assert!((targets[0].position - chain.joints[3]).length() < 1e-4);
+        assert!(matches!(clip.targets(1, &[("tail", 3)]), Err(IkError::Bvh(_))));
+        assert!(matches!(Bvh::parse("HIERARCHY\nMOTION"), Err(IkError::Bvh(_))));
+    }
+
+    #[test]
+    fn test_ccd_and_fabrik_on_same_rig() {
+        let mut fabrik = straight_chain(4, Vec3::Y);
+        fabrik
+            .set_constraint(
+                2,
+                JointConstraint::Hinge {
+                    axis: Vec3::Z,
+                    min: -PI / 2.0,
+                    max: 0.0,
+                },
+            )
+            .unwrap();
+        fabrik.targets.push(Target::new(3, Vec3::new(1.5, 1.5, 0.0)));
+        let mut ccd = fabrik.clone();
+        ccd.solver = Arc::new(Ccd);
+
+        for chain in [&mut fabrik, &mut ccd] {
+            let report = chain
+                .solve(100, PoseDiscrepancy::default(), &mut KinematicsMode::default())
+                .unwrap();
+            assert!(report.reached, "{:?} did not reach: {report:?}", chain.solver);
+            assert_eq!(chain.joints[0], Vec3::ZERO);
+            for i in 0..chain.lengths.len() {
+                let length = chain.joints[i].distance(chain.joints[i + 1]);
+                assert!((length - chain.lengths[i]).abs() < 1e-4);
+            }
+            // The hinge only lets the last segment bend clockwise about Z.
+            let before = chain.joints[2] - chain.joints[1];
+            let after = chain.joints[3] - chain.joints[2];
+            assert!(before.cross(after).z <= 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_damped_least_squares() {
+        let mut chain = straight_chain(4, Vec3::Y);
+        chain.solver = Arc::new(DampedLeastSquares::new(0.2));
+        let hinge = JointConstraint::Hinge {
+            axis: Vec3::Z,
+            min: -PI / 2.0,
+            max: 0.0,
+        };
+        chain.set_constraint(1, hinge).unwrap();
+        chain.set_constraint(2, hinge).unwrap();
+        // Reach (1.5, 1.5) with the last segment pointing along +X.
+        let facing_x = Quat::from_rotation_z(-PI / 2.0);
+        chain
+            .targets
+            .push(Target::new(3, Vec3::new(1.5, 1.5, 0.0)).with_orientation(facing_x, 1.0));
+        let report = chain
+            .solve(200, PoseDiscrepancy::default(), &mut KinematicsMode::default())
+            .unwrap();
+        assert!(report.reached, "{report:?}");
+        assert_eq!(chain.joints[0], Vec3::ZERO);
+        for i in 0..chain.lengths.len() {
+            let length = chain.joints[i].distance(chain.joints[i + 1]);
+            assert!((length - chain.lengths[i]).abs() < 1e-4);
+        }
+        // Both hinges stay in the XY plane and only bend clockwise.
+        for i in 1..3 {
+            let before = chain.joints[i] - chain.joints[i - 1];
+            let after = chain.joints[i + 1] - chain.joints[i];
+            assert!(chain.joints[i + 1].z.abs() < 1e-4);
+            assert!(before.cross(after).z <= 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_batch_matches_single_chains() {
+        let solvers: [Arc<dyn Solver>; 3] = [
+            Arc::new(Fabrik),
+            Arc::new(Ccd),
+            Arc::new(DampedLeastSquares::default()),
+        ];
+        let mut chains = Vec::new();
+        for (n, solver) in solvers.into_iter().enumerate() {
+            let joints = (0..n + 3)
+                .map(|i| Vec3::new(0.0, i as f32, 0.0))
+                .collect();
+            let mut chain = FabrikChain::new(joints, MotionHeuristics::default()).unwrap();
+            chain.solver = solver;
+            chain.clock = Clock::FixedStep(Duration::from_millis(16));
+            chain.targets.push(Target::new(n + 2, Vec3::new(1.0, 1.5, 0.5)));
+            chains.push(chain);
+        }
+        chains[1].lock_ground = false;
+        chains[2].targets.push(Target::new(1, Vec3::new(0.5, 0.5, 0.0)));
+
+        let mut batch = ChainBatch::new();
+        for chain in &chains {
+            batch.push(chain).unwrap();
+        }
+        batch.set_targets(0, &[Target::new(2, Vec3::new(-1.0, 1.0, 0.0))]).unwrap();
+        chains[0].targets = vec![Target::new(2, Vec3::new(-1.0, 1.0, 0.0))];
+        assert!(batch.set_targets(0, &[Target::new(3, Vec3::ZERO)]).is_err());
+
+        for _ in 0..2 {
+            let reports = batch.solve(10);
+            for (i, chain) in chains.iter_mut().enumerate() {
+                let report = chain
+                    .solve(10, PoseDiscrepancy::default(), &mut KinematicsMode::default())
+                    .unwrap();
+                let batched = reports[i].as_ref().unwrap();
+                assert_eq!(batched.iterations, report.iterations);
+                assert_eq!(batched.residuals, report.residuals);
+                assert_eq!(batch.joints(i), Some(chain.joints.as_slice()));
+                #[cfg(feature = "bevy_transform")]
+                assert_eq!(
+                    batch.segment_transforms(i),
+                    Some(chain.segment_transforms.as_slice())
+                );
+                assert_eq!(
+                    batch.angular_velocities(i),
+                    Some(chain.angular_velocities.as_slice())
+                );
+            }
+        }
+        let rebuilt = batch.chain(2).unwrap();
+        assert_eq!(rebuilt.joints, chains[2].joints);
+        assert_eq!(rebuilt.targets, chains[2].targets);
+        assert!(batch.chain(3).is_err());
+        assert_eq!(batch.joints(3), None);
+        #[cfg(feature = "bevy_transform")]
+        assert_eq!(batch.segment_transforms(3), None);
+        assert_eq!(batch.angular_velocities(3), None);
+        assert_eq!(batch.targets(3), None);
+        assert!(batch.targets_mut(3).is_none());
+
+        #[cfg(feature = "rayon")]
+        {
+            let mut sequential = batch.clone();
+            let reports = batch.par_solve(10);
+            for (i, report) in sequential.solve(10).into_iter().enumerate() {
+                assert_eq!(report.unwrap().residuals, reports[i].as_ref().unwrap().residuals);
+                assert_eq!(sequential.joints(i), batch.joints(i));
+            }
+        }
+    }
+
+    #[test]
+    fn test_double_precision_chain() {
+        // A 3 m arm in millimetres, mounted 100 m from the origin, where f32 only resolves
+        // to about 8 µm.
+        let base = Vec3::new(100_000.0, 0.0, 0.0);
+        let joints = (0..4).map(|i| base + Vec3::Y * 1000.0 * i as f32).collect();
+        let mut chain = FabrikChain::new(joints, MotionHeuristics::default()).unwrap();
+        chain.ground = base;
+        let hinge = JointConstraint::Hinge {
+            axis: Vec3::Z,
+            min: -PI / 2.0,
+            max: 0.0,
+        };
+        chain.set_constraint(1, hinge).unwrap();
+        chain.set_constraint(2, hinge).unwrap();
+        chain
+            .targets
+            .push(Target::new(3, base + Vec3::new(1500.0, 1500.0, 0.0)));
+
+        let mut precise = DFabrikChain::from_chain(&chain).unwrap();
+        assert_eq!(precise.tolerance, 1e-6);
+        let report = precise.solve(200).unwrap();
+        assert!(report.reached, "{report:?}");
+        let single = chain
+            .solve(200, PoseDiscrepancy::default(), &mut KinematicsMode::default())
+            .unwrap();
+        assert!(report.max_residual() < single.max_residual());
+
+        assert_eq!(precise.joints[0], base.as_dvec3());
+        for i in 0..precise.lengths.len() {
+            let length = precise.joints[i].distance(precise.joints[i + 1]);
+            assert!((length - precise.lengths[i]).abs() < 1e-9);
+        }
+        for i in 1..3 {
+            let before = precise.joints[i] - precise.joints[i - 1];
+            let after = precise.joints[i + 1] - precise.joints[i];
+            assert!(before.cross(after).z <= 1e-9);
+        }
+
+        precise.apply_to(&mut chain).unwrap();
+        assert!(chain.joints[3].distance(chain.targets[0].position) < 1e-2);
+
+        // What the passes here can't follow is refused rather than dropped.
+        let mut anchored = chain.clone();
+        let anchor = (1, base + Vec3::Y * 1000.0, Quat::IDENTITY);
+        anchored.motion_heuristics.anchor_points.push(anchor);
+        assert_eq!(
+            DFabrikChain::from_chain(&anchored).unwrap_err(),
+            IkError::DoublePrecisionUnsupported("anchor points")
+        );
+        let mut weighted = chain.clone();
+        weighted.targets[0].position_weight = 0.5;
+        assert_eq!(
+            DFabrikChain::from_chain(&weighted).unwrap_err(),
+            IkError::DoublePrecisionUnsupported("weighted targets")
+        );
+        let mut oriented = chain.clone();
+        oriented.targets[0].orientation = Some(Quat::IDENTITY);
+        assert_eq!(
+            DFabrikChain::from_chain(&oriented).unwrap_err(),
+            IkError::DoublePrecisionUnsupported("orientation targets")
+        );
+        let mut empty = precise.clone();
+        empty.joints.clear();
+        empty.lengths.clear();
+        empty.targets.clear();
+        empty.fwd_reach();
+        empty.bwd_reach();
+        assert_eq!(empty.solve(1).unwrap_err(), IkError::EmptyChain);
+        let mut stub = FabrikChain::new(vec![Vec3::ZERO], MotionHeuristics::default()).unwrap();
+        assert_eq!(
+            precise.apply_to(&mut stub).unwrap_err(),
+            IkError::MismatchedJointCount {
+                expected: 4,
+                found: 1
+            }
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "bevy")]
+    fn test_bevy_plugin() {
+        use bevy_app::App;
+        use bevy_ecs::entity::Entity;
+        use bevy_hierarchy::BuildWorldChildren;
+        use bevy_transform::prelude::{GlobalTransform, TransformBundle, TransformPlugin};
+
+        let mut app = App::new();
+        app.add_plugins((TransformPlugin, IkPlugin));
+        let world = &mut app.world;
+        // The arm hangs off a parent that is moved and turned, with an extra entity between
+        // the last two bones.
+        let parent = Transform::from_xyz(5.0, 0.0, 0.0).with_rotation(Quat::from_rotation_z(0.3));
+        let base = world.spawn(TransformBundle::from_transform(parent)).id();
+        let mut bones = Vec::new();
+        let mut previous = base;
+        for i in 0..4 {
+            let offset = if i == 0 { Vec3::ZERO } else { Vec3::Y };
+            let bone = world
+                .spawn(TransformBundle::from_transform(Transform::from_translation(offset)))
+                .id();
+            world.entity_mut(previous).add_child(bone);
+            previous = bone;
+            if i == 2 {
+                let between = world.spawn(TransformBundle::default()).id();
+                world.entity_mut(bone).add_child(between);
+                previous = between;
+            }
+            bones.push(bone);
+        }
+        let goal = Vec3::new(5.0, 2.0, 1.0);
+        let target = world
+            .spawn(TransformBundle::from_transform(Transform::from_translation(goal)))
+            .id();
+        let arm = world
+            .spawn(IkChain::new(bones.clone(), target).with_iterations(50))
+            .id();
+
+        let position = |app: &App, bone: Entity| {
+            app.world.get::<GlobalTransform>(bone).unwrap().translation()
+        };
+        let root = parent.translation;
+        for _ in 0..2 {
+            app.update();
+            let report = app.world.get::<IkChain>(arm).unwrap().report.clone();
+            assert!(report.unwrap().unwrap().reached);
+            assert!(position(&app, bones[3]).distance(goal) < 1e-2);
+            assert!(position(&app, bones[0]).distance(root) < 1e-5);
+            for pair in bones.windows(2) {
+                let length = position(&app, pair[0]).distance(position(&app, pair[1]));
+                assert!((length - 1.0).abs() < 1e-4);
+            }
+        }
+
+        app.world.entity_mut(target).remove::<Transform>();
+        app.update();
+        let report = app.world.get::<IkChain>(arm).unwrap().report.clone();
+        assert!(matches!(report, Some(Err(IkError::Bevy(_)))));
+    }
+
+    #[test]
+    #[cfg(feature = "bevy")]
+    fn test_bevy_plugin_free_ground() {
+        use bevy_app::App;
+        use bevy_ecs::entity::Entity;
+        use bevy_hierarchy::BuildWorldChildren;
+        use bevy_transform::prelude::{GlobalTransform, TransformBundle, TransformPlugin};
+
+        let mut app = App::new();
+        app.add_plugins((TransformPlugin, IkPlugin));
+        let world = &mut app.world;
+        let mut bones: Vec<Entity> = Vec::new();
+        for i in 0..3 {
+            let offset = if i == 0 { Vec3::ZERO } else { Vec3::Y };
+            let bone = world
+                .spawn(TransformBundle::from_transform(Transform::from_translation(offset)))
+                .id();
+            if let Some(previous) = bones.last() {
+                world.entity_mut(*previous).add_child(bone);
+            }
+            bones.push(bone);
+        }
+        // Out of reach, so the free ground is dragged along behind the tip.
+        let goal = Vec3::new(4.0, 1.0, 0.0);
+        let target = world
+            .spawn(TransformBundle::from_transform(Transform::from_translation(goal)))
+            .id();
+        let mut chain = straight_chain(3, Vec3::Y);
+        chain.lock_ground = false;
+        let arm = world
+            .spawn(
+                IkChain::new(bones.clone(), target)
+                    .with_chain(chain)
+                    .with_iterations(50),
+            )
+            .id();
+
+        app.update();
+        let position = |bone: Entity| {
+            app.world.get::<GlobalTransform>(bone).unwrap().translation()
+        };
+        let ik = app.world.get::<IkChain>(arm).unwrap();
+        assert!(ik.report.clone().unwrap().unwrap().reached);
+        assert!(position(bones[0]).distance(Vec3::ZERO) > 1.0);
+        for (bone, joint) in bones.iter().zip(&ik.chain.as_ref().unwrap().joints) {
+            assert!(position(*bone).distance(*joint) < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_fabrik_solve() {
+        let joints = vec![
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(2.0, 0.0, 0.0),
+        ];
+        let motion_heuristics = MotionHeuristics {
+            anchor_points: Vec::new(),
+            parent_ranking: Vec::new(),
+        };
+
+        let mut chain = FabrikChain::new(joints, motion_heuristics).unwrap();
+        let target = Vec3::new(1.0, 1.0, 0.0);
+        chain.targets.push(Target::new(2, target));
+
+        chain
+            .solve(10, PoseDiscrepancy::default(), &mut KinematicsMode::default())
+            .unwrap();
+
+        assert!((*chain.joints.last().unwrap() - target).length() < 0.01);
+    }
+
+    /// Invariants of the FABRIK passes over random chains, constraints and targets.
+    mod properties {
+        use super::*;
+        use proptest::prelude::*;
+
+        fn point(extent: f32) -> impl Strategy<Value = Vec3> {
+            (-extent..extent, -extent..extent, -extent..extent)
+                .prop_map(|(x, y, z)| Vec3::new(x, y, z))
+        }
+
+        fn direction() -> impl Strategy<Value = Vec3> {
+            point(1.0)
+                .prop_filter("too short to normalize", |v| v.length() > 0.1)
+                .prop_map(Vec3::normalize)
+        }
+
+        fn constraint() -> impl Strategy<Value = JointConstraint> {
+            prop_oneof![
+                Just(JointConstraint::Unconstrained),
+                (direction(), -PI..0.0, 0.0..PI)
+                    .prop_map(|(axis, min, max)| JointConstraint::Hinge { axis, min, max }),
+                (0.1..PI).prop_map(|max_swing| JointConstraint::BallSocket { max_swing }),
+            ]
+        }
+
+        /// Lays out segments of `lengths` from `joints[root]` along `directions`, both ways.
+        fn lay_out(joints: &mut [Vec3], root: usize, lengths: &[f32], directions: &[Vec3]) {
+            for i in root..lengths.len() {
+                joints[i + 1] = joints[i] + directions[i] * lengths[i];
+            }
+            for i in (0..root).rev() {
+                joints[i] = joints[i + 1] - directions[i] * lengths[i];
+            }
+        }
+
+        /// An unconstrained chain of 2 to 8 joints, with segments 0.1 to 2 long, a random root
+        /// and the ground where that root is.
+        fn chain() -> impl Strategy<Value = FabrikChain> {
+            (
+                point(10.0),
+                prop::collection::vec((direction(), 0.1f32..2.0), 1..8),
+                any::<prop::sample::Index>(),
+            )
+                .prop_map(|(origin, segments, root)| {
+                    let mut joints = vec![origin];
+                    for (direction, length) in segments {
+                        joints.push(joints[joints.len() - 1] + direction * length);
+                    }
+                    let mut chain =
+                        FabrikChain::new(joints, MotionHeuristics::default()).unwrap();
+                    chain.root = root.index(chain.joints.len());
+                    chain.ground = chain.joints[chain.root];
+                    chain.snapshot();
+                    chain
+                })
+        }
+
+        /// A chain bent at every joint. FABRIK can't leave the line a straight or folded chain
+        /// lies on when its target lies on that line too, so only these are sure to converge.
+        fn bent_chain() -> impl Strategy<Value = FabrikChain> {
+            chain().prop_filter("straight or folded", |chain| {
+                chain.joints.windows(3).all(|joints| {
+                    let before = (joints[1] - joints[0]).normalize();
+                    let after = (joints[2] - joints[1]).normalize();
+                    before.cross(after).length() > 0.1
+                })
+            })
+        }
+
+        /// A chain with a random constraint on every joint.
+        fn constrained_chain() -> impl Strategy<Value = FabrikChain> {
+            (chain(), prop::collection::vec(constraint(), 8)).prop_map(|(mut chain, constraints)| {
+                let len = chain.joints.len();
+                for (i, constraint) in constraints.into_iter().take(len).enumerate() {
+                    chain.set_constraint(i, constraint).unwrap();
+                }
+                chain
+            })
+        }
+
+        /// Up to three targets anywhere, reachable or not.
+        fn targets() -> impl Strategy<Value = Vec<(prop::sample::Index, Vec3)>> {
+            prop::collection::vec((any::<prop::sample::Index>(), point(20.0)), 1..4)
+        }
+
+        fn with_targets(chain: &mut FabrikChain, targets: Vec<(prop::sample::Index, Vec3)>) {
+            for (index, position) in targets {
+                let index = index.index(chain.joints.len());
+                chain.targets.push(Target::new(index, position));
+            }
+        }
+
+        fn assert_lengths_kept(chain: &FabrikChain) -> Result<(), TestCaseError> {
+            for (i, length) in chain.lengths.iter().enumerate() {
+                let actual = chain.joints[i].distance(chain.joints[i + 1]);
+                prop_assert!(
+                    (actual - length).abs() <= 1e-4 * length.max(1.0),
+                    "segment {i} is {actual} long instead of {length}"
+                );
+            }
+            Ok(())
+        }
+
+        proptest! {
+            #[test]
+            fn reaching_keeps_segment_lengths(
+                mut chain in constrained_chain(),
+                targets in targets(),
+            ) {
+                with_targets(&mut chain, targets);
+                for target in chain.targets.clone() {
+                    chain.joints[target.index] = target.position;
+                }
+                chain.fwd_reach();
+                assert_lengths_kept(&chain)?;
+                chain.bwd_reach();
+                assert_lengths_kept(&chain)?;
+            }
+
+            #[test]
+            fn locked_ground_stays_pinned(
+                mut chain in constrained_chain(),
+                targets in targets(),
+            ) {
+                with_targets(&mut chain, targets);
+                chain.lock_ground = true;
+                let ground = chain.ground;
+                chain
+                    .solve(50, PoseDiscrepancy::default(), &mut KinematicsMode::default())
+                    .unwrap();
+                prop_assert!(chain.joints[chain.root].distance(ground) <= 1e-5);
+            }
+
+            #[test]
+            fn reachable_targets_converge(
+                mut chain in bent_chain(),
+                directions in prop::collection::vec(direction(), 7),
+            ) {
+                // Somewhere the chain itself can be posed to put its end effector. Joints past a
+                // target in the middle of the chain drag it off again on every pass, so only the
+                // end away from the root is sure to get there. FABRIK crawls towards a target
+                // the chain only just reaches, stretched out or folded up, so the target is
+                // kept a little way inside either limit.
+                let mut pose = chain.joints.clone();
+                lay_out(&mut pose, chain.root, &chain.lengths, &directions);
+                let index = if chain.root == pose.len() - 1 { 0 } else { pose.len() - 1 };
+                let side = if index == 0 {
+                    &chain.lengths[..chain.root]
+                } else {
+                    &chain.lengths[chain.root..]
+                };
+                let reach: f32 = side.iter().sum();
+                let folded = (2.0 * side.iter().copied().fold(0.0, f32::max) - reach).max(0.0);
+                let slack = 0.1 * (reach - folded);
+                let offset = pose[index] - pose[chain.root];
+                let distance = offset.length().clamp(folded + slack, reach - slack);
+                pose[index] = pose[chain.root] + offset.normalize() * distance;
+                chain.targets.push(Target::new(index, pose[index]));
+                let report = chain
+                    .solve(1000, PoseDiscrepancy::default(), &mut KinematicsMode::default())
+                    .unwrap();
+                prop_assert!(report.reached, "{report:?}");
+                assert_lengths_kept(&chain)?;
+            }
+
+            #[test]
+            fn solving_never_produces_nan(
+                mut chain in constrained_chain(),
+                targets in targets(),
+                lock_ground: bool,
+            ) {
+                chain.lock_ground = lock_ground;
+                with_targets(&mut chain, targets);
+                let report = chain
+                    .solve(50, PoseDiscrepancy::default(), &mut KinematicsMode::default())
+                    .unwrap();
+                prop_assert!(chain.joints.iter().all(|joint| joint.is_finite()));
+                prop_assert!(chain.angles.iter().all(|angle| angle.is_finite()));
+                prop_assert!(chain.segment_frames().iter().all(|frame| frame.is_finite()));
+                prop_assert!(report.residuals.iter().all(|(_, residual)| residual.is_finite()));
+            }
+
+            #[test]
+            fn reset_restores_initial_pose(
+                mut chain in constrained_chain(),
+                targets in targets(),
+            ) {
+                let initial = chain.clone();
+                with_targets(&mut chain, targets);
+                chain
+                    .solve(50, PoseDiscrepancy::default(), &mut KinematicsMode::default())
+                    .unwrap();
+                chain.reset().unwrap();
+                prop_assert_eq!(&chain.joints, &initial.joints);
+                prop_assert_eq!(&chain.lengths, &initial.lengths);
+                prop_assert_eq!(chain.root, initial.root);
+                prop_assert!(chain.targets.is_empty());
+            }
+        }
+    }
 }
@@ -0,0 +1,41 @@
+//! Float functions that `core` leaves to `std`, taken from `libm` when building without it.
+//! Only imported without `std`, so the inherent methods win whenever they exist.
+
+#[cfg(not(feature = "std"))]
+// Unit tests link `std`, whose inherent methods then shadow these.
+#[cfg_attr(test, allow(dead_code))]
+pub trait Float: Sized {
+    fn sqrt(self) -> Self;
+    fn powi(self, n: i32) -> Self;
+    fn atan2(self, other: Self) -> Self;
+}
+
+#[cfg(not(feature = "std"))]
+impl Float for f32 {
+    fn sqrt(self) -> Self {
+        libm::sqrtf(self)
+    }
+
+    fn powi(self, n: i32) -> Self {
+        libm::powf(self, n as f32)
+    }
+
+    fn atan2(self, other: Self) -> Self {
+        libm::atan2f(self, other)
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl Float for f64 {
+    fn sqrt(self) -> Self {
+        libm::sqrt(self)
+    }
+
+    fn powi(self, n: i32) -> Self {
+        libm::pow(self, n as f64)
+    }
+
+    fn atan2(self, other: Self) -> Self {
+        libm::atan2(self, other)
+    }
+}
@@ -0,0 +1,202 @@
+//! Bevy integration: put an `IkChain` on an entity, point it at bone entities and a target
+//! entity, and `IkPlugin` poses the bones every frame.
+//!
+//! ```no_run
+//! use bevy_app::App;
+//! use bevy_ecs::prelude::*;
+//! use bevy_hierarchy::BuildWorldChildren;
+//! use bevy_transform::prelude::*;
+//! use ik3::{IkChain, IkPlugin};
+//!
+//! let mut app = App::new();
+//! app.add_plugins((TransformPlugin, IkPlugin));
+//! let world = &mut app.world;
+//! let target = world
+//!     .spawn(TransformBundle::from_transform(Transform::from_xyz(1.0, 1.0, 0.0)))
+//!     .id();
+//! let mut bones = vec![world.spawn(TransformBundle::default()).id()];
+//! for _ in 0..2 {
+//!     let bone = world
+//!         .spawn(TransformBundle::from_transform(Transform::from_xyz(0.0, 1.0, 0.0)))
+//!         .id();
+//!     world.entity_mut(bones[bones.len() - 1]).add_child(bone);
+//!     bones.push(bone);
+//! }
+//! world.spawn(IkChain::new(bones, target));
+//! app.update();
+//! ```
+//!
+//! Solving happens in `IkSet`, in `PostUpdate` before transforms are propagated, so moves made
+//! to the target during `Update` are followed in the same frame and the posed bones' global
+//! transforms are up to date by the end of it.
+
+use crate::extern_prelude::*;
+use crate::{FabrikChain, IkError, KinematicsMode, MotionHeuristics, PoseDiscrepancy};
+use crate::{SolveReport, Target};
+use bevy_app::{App, Plugin, PostUpdate};
+use bevy_ecs::prelude::*;
+use bevy_hierarchy::Parent;
+use bevy_transform::TransformSystem;
+use std::collections::HashMap;
+
+/// Solves every `IkChain` once per frame.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IkPlugin;
+
+impl Plugin for IkPlugin {
+    fn build(&self, app: &mut App) {
+        app.configure_set(
+            PostUpdate,
+            IkSet.before(TransformSystem::TransformPropagate),
+        )
+        .add_systems(PostUpdate, solve_chains.in_set(IkSet));
+    }
+}
+
+/// Where `IkPlugin` solves its chains and writes the bones back, in `PostUpdate`. Order
+/// systems that read the posed bones' `Transform`s after it.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct IkSet;
+
+/// A chain of bone entities pulled towards a target entity.
+///
+/// Every frame the bones' current world positions become the chain's joints, with the first
+/// bone as the ground, and the last bone is pulled to the target's world position. Each bone
+/// is then turned by the rotation its segment turned through, and the last bone turns with the
+/// segment leading to it. Only bone rotations change, apart from the first bone's translation
+/// when the solve moves it.
+#[derive(Component, Debug, Clone)]
+pub struct IkChain {
+    /// Bones from the root of the chain to its tip. Each has to be a descendant of the one
+    /// before it, though not necessarily a direct child.
+    pub bones: Vec<Entity>,
+    pub target: Entity,
+    /// Passes per frame.
+    pub iterations: usize,
+    /// Constraints, solver and settings to solve with. Its joints, lengths, ground and targets
+    /// are taken from the bones and the target every frame. Built from the bones with default
+    /// settings on the first frame if not given.
+    pub chain: Option<FabrikChain>,
+    /// Outcome of the last frame's solve.
+    pub report: Option<Result<SolveReport, IkError>>,
+}
+
+impl IkChain {
+    pub fn new(bones: Vec<Entity>, target: Entity) -> Self {
+        Self {
+            bones,
+            target,
+            iterations: 10,
+            chain: None,
+            report: None,
+        }
+    }
+
+    /// Solves with `chain`'s constraints, solver and settings. It has to have a joint per bone.
+    /// Any `targets` it holds are replaced every frame by the one pulling the last bone to the
+    /// target entity.
+    pub fn with_chain(mut self, chain: FabrikChain) -> Self {
+        self.chain = Some(chain);
+        self
+    }
+
+    pub fn with_iterations(mut self, iterations: usize) -> Self {
+        self.iterations = iterations;
+        self
+    }
+}
+
+type Bones<'w, 's> = Query<'w, 's, (&'static mut Transform, Option<&'static Parent>)>;
+
+fn solve_chains(mut chains: Query<&mut IkChain>, mut bones: Bones) {
+    for mut ik in chains.iter_mut() {
+        let report = solve_chain(&mut ik, &mut bones);
+        ik.report = Some(report);
+    }
+}
+
+fn solve_chain(ik: &mut IkChain, bones: &mut Bones) -> Result<SolveReport, IkError> {
+    let mut known = HashMap::new();
+    let mut parents = Vec::with_capacity(ik.bones.len());
+    let mut before = Vec::with_capacity(ik.bones.len());
+    for (index, bone) in ik.bones.iter().enumerate() {
+        let (local, parent) = bones
+            .get(*bone)
+            .map_err(|_| IkError::Bevy(format!("bone {index} has no Transform")))?;
+        let parent = parent.and_then(|parent| world_transform(parent.get(), bones, &mut known));
+        before.push(parent.map_or(*local, |parent| parent.mul_transform(*local)));
+        parents.push(parent);
+    }
+    let target = world_transform(ik.target, bones, &mut known)
+        .ok_or_else(|| IkError::Bevy("the target has no Transform".to_string()))?
+        .translation;
+    let rest: Vec<Vec3> = before.iter().map(|bone| bone.translation).collect();
+
+    if ik.chain.is_none() {
+        ik.chain = Some(FabrikChain::new(
+            rest.clone(),
+            MotionHeuristics::default(),
+        )?);
+    }
+    let chain = ik.chain.as_mut().expect("built above");
+    if chain.joints.len() != rest.len() {
+        return Err(IkError::Bevy(format!(
+            "chain has {} joints but there are {} bones",
+            chain.joints.len(),
+            rest.len()
+        )));
+    }
+    chain.lengths = rest
+        .windows(2)
+        .map(|pair| pair[0].distance(pair[1]))
+        .collect();
+    chain.joints.clone_from(&rest);
+    chain.ground = chain.joints[chain.root];
+    chain.targets = vec![Target::new(chain.joints.len() - 1, target)];
+    let report = chain.solve(
+        ik.iterations,
+        PoseDiscrepancy::WithinTolerance,
+        &mut KinematicsMode::default(),
+    )?;
+
+    // Everything between one bone and the next turns with the first of them, so a bone's
+    // parent has turned through whatever the bone before it did.
+    let turns = chain.turns_since(&rest)?;
+    let mut parent_turn = Quat::IDENTITY;
+    for (k, (bone, turn)) in ik.bones.iter().zip(turns).enumerate() {
+        let rotation = (turn * before[k].rotation).normalize();
+        let parent = parents[k].unwrap_or_default();
+        if let Ok((mut local, _)) = bones.get_mut(*bone) {
+            local.rotation = (parent_turn * parent.rotation).inverse() * rotation;
+            // The rest of the chain hangs off the first bone, which only moves if the ground
+            // is free or the root is further along.
+            if k == 0 && chain.joints[0] != before[0].translation {
+                local.translation = parent.rotation.inverse()
+                    * (chain.joints[0] - parent.translation)
+                    / parent.scale;
+            }
+        }
+        parent_turn = turn;
+    }
+    Ok(report)
+}
+
+/// World transform of `entity` from the local transforms up its hierarchy, so it reflects
+/// changes made this frame that have not been propagated yet. Entities already in `known` are
+/// not walked again.
+fn world_transform(
+    entity: Entity,
+    bones: &Bones,
+    known: &mut HashMap<Entity, Transform>,
+) -> Option<Transform> {
+    if let Some(transform) = known.get(&entity) {
+        return Some(*transform);
+    }
+    let (local, parent) = bones.get(entity).ok()?;
+    let transform = match parent.and_then(|parent| world_transform(parent.get(), bones, known)) {
+        Some(parent) => parent.mul_transform(*local),
+        None => *local,
+    };
+    known.insert(entity, transform);
+    Some(transform)
+}
@@ -0,0 +1,101 @@
+use crate::extern_prelude::*;
+use crate::{FabrikChain, IkError, JointConstraint, Target};
+
+impl FabrikChain {
+    /// Where the root is held during solving, or `None` if it is free to move.
+    pub fn pinned_root(&self) -> Option<Vec3> {
+        self.lock_ground
+            .then(|| self.anchor_position(self.root).unwrap_or(self.ground))
+    }
+
+    fn check(&self, index: usize) -> Result<(), IkError> {
+        if index >= self.joints.len() {
+            return Err(IkError::JointOutOfRange {
+                index,
+                len: self.joints.len(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Total length of the segments between the root and joint `index`.
+    pub fn reach(&self, index: usize) -> Result<f32, IkError> {
+        self.check(index)?;
+        let (from, to) = (self.root.min(index), self.root.max(index));
+        Ok(self.lengths[from..to].iter().sum())
+    }
+
+    /// Whether joint `index` can get to `position` without leaving the root behind. Anything
+    /// is reachable when the root is not pinned.
+    pub fn is_reachable(&self, index: usize, position: Vec3) -> Result<bool, IkError> {
+        let reach = self.reach(index)?;
+        Ok(match self.pinned_root() {
+            Some(root) => root.distance(position) <= reach,
+            None => true,
+        })
+    }
+
+    /// `position` pulled in onto the shell joint `index` can reach, if it lies beyond it.
+    pub fn project_to_reachable(&self, index: usize, position: Vec3) -> Result<Vec3, IkError> {
+        let reach = self.reach(index)?;
+        Ok(match self.pinned_root() {
+            Some(root) => root + (position - root).clamp_length_max(reach),
+            None => position,
+        })
+    }
+
+    /// Whether `target` is out of reach and a straight chain is the best pose for it: it is
+    /// on the joint furthest from the root on its side and has no orientation, there are no
+    /// anchor points, and no joint is constrained.
+    pub fn can_straighten_towards(&self, target: &Target) -> bool {
+        let far_end = if target.index > self.root {
+            target.index + 1 == self.joints.len()
+        } else {
+            target.index == 0 && self.root > 0
+        };
+        far_end
+            && target.orientation.is_none()
+            && self.motion_heuristics.anchor_points.is_empty()
+            && self
+                .constraints
+                .iter()
+                .all(|constraint| *constraint == JointConstraint::Unconstrained)
+            && self.is_reachable(target.index, target.position) == Ok(false)
+    }
+
+    /// Lays the chain out in a straight line from the root towards `position`, which is the
+    /// best joint `index` can do for a target out of reach when `can_straighten_towards`
+    /// holds. Joints beyond `index` carry on along the same line, and constraints and anchor
+    /// points are ignored.
+    pub fn straighten_towards(&mut self, index: usize, position: Vec3) -> Result<(), IkError> {
+        self.check(index)?;
+        let root = self.pinned_root().unwrap_or(self.joints[self.root]);
+        let Some(direction) = (position - root).try_normalize() else {
+            return Ok(());
+        };
+        // Walking away from the root towards `index` and past it means going up the chain if
+        // `index` is above the root, and down it otherwise.
+        let direction = if index >= self.root {
+            direction
+        } else {
+            -direction
+        };
+        self.joints[self.root] = root;
+        for i in self.root..self.lengths.len() {
+            self.joints[i + 1] = self.joints[i] + direction * self.lengths[i];
+        }
+        for i in (0..self.root).rev() {
+            self.joints[i] = self.joints[i + 1] - direction * self.lengths[i];
+        }
+        Ok(())
+    }
+
+    /// Indices of the targets that lie out of reach, or on a joint past the end of the chain.
+    pub fn unreachable_targets(&self) -> Vec<usize> {
+        self.targets
+            .iter()
+            .filter(|target| self.is_reachable(target.index, target.position) != Ok(true))
+            .map(|target| target.index)
+            .collect()
+    }
+}
@@ -0,0 +1,139 @@
+use crate::extern_prelude::*;
+use crate::{FabrikChain, IkError};
+
+/// Tuning for the `PoseDiscrepancy::SevereDivergence` recovery.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RecoverySettings {
+    /// How many times the requested iteration count a recovery solve may use.
+    pub iteration_multiplier: usize,
+    /// Furthest any joint may move in a single recovery solve, in chain units. Keeps the
+    /// commanded pose from snapping back all at once.
+    pub max_step: f32,
+}
+
+impl Default for RecoverySettings {
+    fn default() -> Self {
+        Self {
+            iteration_multiplier: 4,
+            max_step: 0.25,
+        }
+    }
+}
+
+/// How the chain was re-seeded before re-solving.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecoveryStrategy {
+    /// Solved again starting from the fantasy limb's pose.
+    ReseededFromFantasy,
+    /// The fantasy pose did not reach the targets, so solved again from the initial pose.
+    ResolvedFromScratch,
+}
+
+/// What a severe-divergence recovery did, for the caller to log.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Recovery {
+    pub strategy: RecoveryStrategy,
+    /// Largest distance between a real and a fantasy joint before recovering.
+    pub divergence: f32,
+    /// Whether `RecoverySettings::max_step` held back part of the correction.
+    pub rate_limited: bool,
+}
+
+impl FabrikChain {
+    pub(crate) fn recover(&mut self, iterations: usize) -> Result<(usize, Recovery), IkError> {
+        let fantasy_joints = self
+            .fantasy_limb
+            .as_ref()
+            .ok_or(IkError::MissingFantasyLimb)?
+            .joints
+            .clone();
+        if fantasy_joints.len() != self.joints.len() {
+            return Err(IkError::FantasyLimbMismatch {
+                joints: self.joints.len(),
+                fantasy_joints: fantasy_joints.len(),
+            });
+        }
+        let divergence = self
+            .joints
+            .iter()
+            .zip(fantasy_joints.iter())
+            .map(|(joint, fantasy_joint)| joint.distance(*fantasy_joint))
+            .fold(0.0, f32::max);
+        let prior = self.joints.clone();
+        let budget = iterations * self.recovery.iteration_multiplier;
+
+        self.joints = fantasy_joints;
+        let mut iterations_used = self.iterate(budget);
+        let mut strategy = RecoveryStrategy::ReseededFromFantasy;
+
+        if !self.targets_reached() {
+            if let Some(initial_joints) = self.initial_state.as_ref().map(|s| s.joints.clone()) {
+                let reseeded_residual = self.report(0, Duration::ZERO).max_residual();
+                let reseeded = core::mem::replace(&mut self.joints, initial_joints);
+                iterations_used += self.iterate(budget);
+                if self.report(0, Duration::ZERO).max_residual() < reseeded_residual {
+                    strategy = RecoveryStrategy::ResolvedFromScratch;
+                } else {
+                    self.joints = reseeded;
+                }
+            }
+        }
+
+        let rate_limited = self.limit_step(&prior);
+
+        Ok((
+            iterations_used,
+            Recovery {
+                strategy,
+                divergence,
+                rate_limited,
+            },
+        ))
+    }
+
+    /// Moves the chain only part of the way from `prior` to its current pose if getting all
+    /// the way there would move some joint further than `max_step`. The root and every
+    /// segment's direction and length are interpolated by the same fraction, working out from
+    /// the root, so segments stay whole and no joint moves further than `max_step`. Returns
+    /// whether the pose was held back.
+    fn limit_step(&mut self, prior: &[Vec3]) -> bool {
+        let root = self.root;
+        let before: Vec<Vec3> = prior.windows(2).map(|pair| pair[1] - pair[0]).collect();
+        let after: Vec<Vec3> = self.joints.windows(2).map(|pair| pair[1] - pair[0]).collect();
+        // Taking a fraction `t` of the way moves a segment's far end, relative to its near
+        // end, by at most `t` times this much: the arc its direction sweeps plus its change
+        // in length.
+        let segment_step = |i: usize| {
+            let (a, b) = (before[i].length(), after[i].length());
+            before[i].angle_between(after[i]) * a.max(b) + (b - a).abs()
+        };
+        let mut steps = vec![0.0; self.joints.len()];
+        steps[root] = self.joints[root].distance(prior[root]);
+        for i in root..after.len() {
+            steps[i + 1] = steps[i] + segment_step(i);
+        }
+        for i in (0..root).rev() {
+            steps[i] = steps[i + 1] + segment_step(i);
+        }
+        let largest = steps.iter().copied().fold(0.0, f32::max);
+        if largest <= self.recovery.max_step {
+            return false;
+        }
+
+        let t = self.recovery.max_step / largest;
+        let segment = |i: usize| {
+            let (a, b) = (before[i].length(), after[i].length());
+            let turn = Quat::IDENTITY.slerp(Quat::from_rotation_arc(before[i] / a, after[i] / b), t);
+            turn * before[i] / a * (a + (b - a) * t)
+        };
+        self.joints[root] = prior[root].lerp(self.joints[root], t);
+        for i in root..after.len() {
+            self.joints[i + 1] = self.joints[i] + segment(i);
+        }
+        for i in (0..root).rev() {
+            self.joints[i] = self.joints[i + 1] - segment(i);
+        }
+        true
+    }
+}
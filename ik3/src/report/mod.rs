@@ -0,0 +1,70 @@
+use crate::extern_prelude::*;
+use crate::{targets, FabrikChain, Recovery, BONE_AXIS};
+
+/// Outcome of a single call to `FabrikChain::solve`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SolveReport {
+    /// Number of FABRIK iterations actually run, at most the requested count.
+    pub iterations: usize,
+    /// Distance between each target joint and its target position after solving, in the
+    /// same order as `FabrikChain::targets`.
+    pub residuals: Vec<(usize, f32)>,
+    /// Angle between each oriented target's segment and the direction it should face.
+    pub orientation_residuals: Vec<(usize, f32)>,
+    /// Whether every residual ended up within `FabrikChain::tolerance`, and every
+    /// orientation residual within `FabrikChain::orientation_tolerance`.
+    pub reached: bool,
+    /// Wall time spent inside `solve`.
+    pub elapsed: Duration,
+    /// The recovery taken, when solving in `PoseDiscrepancy::SevereDivergence` mode.
+    pub recovery: Option<Recovery>,
+    /// Indices of the targets that were out of reach of the pinned root.
+    pub unreachable: Vec<usize>,
+    /// Whether the chain was straightened towards a lone unreachable target before iterating,
+    /// as `FabrikChain::can_straighten_towards` allows.
+    pub straightened: bool,
+    /// Segments still inside an obstacle of `FabrikChain::environment` after solving.
+    pub contacts: usize,
+}
+
+impl SolveReport {
+    /// Largest residual over all targets, `0.0` if there were none.
+    pub fn max_residual(&self) -> f32 {
+        self.residuals
+            .iter()
+            .map(|(_, residual)| *residual)
+            .fold(0.0, f32::max)
+    }
+}
+
+impl FabrikChain {
+    /// Distance between each target joint and its target position.
+    pub fn residuals(&self) -> Vec<(usize, f32)> {
+        targets::residuals(&self.joints, &self.targets)
+    }
+
+    /// Whether every target is within `tolerance` of its joint and facing the right way.
+    pub fn targets_reached(&self) -> bool {
+        targets::targets_reached(
+            &self.joints,
+            &self.targets,
+            self.tolerance,
+            self.orientation_tolerance,
+            |index| self.joint_orientation(index).map(|orientation| orientation * BONE_AXIS),
+        )
+    }
+
+    pub(crate) fn report(&self, iterations: usize, elapsed: Duration) -> SolveReport {
+        SolveReport {
+            iterations,
+            residuals: self.residuals(),
+            orientation_residuals: self.orientation_residuals(),
+            reached: self.targets_reached(),
+            elapsed,
+            recovery: None,
+            unreachable: self.unreachable_targets(),
+            straightened: false,
+            contacts: self.contacts(),
+        }
+    }
+}
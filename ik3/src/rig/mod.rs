@@ -0,0 +1,211 @@
+//! Saving and loading chains as rig files.
+//!
+//! A rig holds everything needed to rebuild a `FabrikChain`: the rest pose, segment lengths,
+//! joint constraints, motion heuristics and solver settings. With the `serde` feature it can
+//! be read from and written to RON or JSON. A small arm in RON looks like this:
+//!
+//! ```ron
+//! (
+//!     version: 1,
+//!     joints: [(0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (2.0, 0.0, 0.0)],
+//!     lengths: Some([1.0, 1.0]),
+//!     constraints: [
+//!         Unconstrained,
+//!         Hinge(axis: (0.0, 0.0, 1.0), min: 0.0, max: 2.5),
+//!         Unconstrained,
+//!     ],
+//!     motion_heuristics: (anchor_points: [], parent_ranking: []),
+//!     solver: (lock_ground: true, tolerance: 0.001, solver: Some(Ccd)),
+//! )
+//! ```
+//!
+//! Only `version` and `joints` are required. Everything else falls back to the defaults of
+//! `FabrikChain::new`, and `lengths` to the distances between the joints. Fields added in
+//! later versions always get a default, so older rigs keep loading; a rig written by a newer
+//! version than this build understands is rejected with `IkError::UnsupportedRigVersion`.
+
+use crate::extern_prelude::*;
+use crate::{
+    Clock, Environment, FabrikChain, IkError, JointConstraint, MotionHeuristics,
+    RecoverySettings, SolverKind,
+};
+
+/// Rig format version written by this build.
+pub const RIG_VERSION: u32 = 1;
+
+/// The solver settings of a `FabrikChain`, as stored in a rig.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct SolverSettings {
+    pub lock_ground: bool,
+    pub root: usize,
+    pub ground: Vec3,
+    pub up: Vec3,
+    pub tolerance: f32,
+    pub orientation_tolerance: f32,
+    pub clock: Clock,
+    pub recovery: RecoverySettings,
+    pub environment: Environment,
+    /// The chain's solver, or `None` for one defined outside this crate, which can't be
+    /// saved. Applying `None` leaves the chain's solver as it is.
+    pub solver: Option<SolverKind>,
+}
+
+impl Default for SolverSettings {
+    fn default() -> Self {
+        Self {
+            lock_ground: true,
+            root: 0,
+            ground: Vec3::ZERO,
+            up: Vec3::Y,
+            tolerance: 1e-3,
+            orientation_tolerance: 1e-2,
+            clock: Clock::default(),
+            recovery: RecoverySettings::default(),
+            environment: Environment::default(),
+            solver: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rig {
+    pub version: u32,
+    pub joints: Vec<Vec3>,
+    /// Segment lengths, overriding the distances between `joints` when given.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub lengths: Option<Vec<f32>>,
+    /// One per joint, or empty to leave every joint unconstrained.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub constraints: Vec<JointConstraint>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub motion_heuristics: MotionHeuristics,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub solver: SolverSettings,
+}
+
+impl Rig {
+    /// Brings a rig read from disk up to `RIG_VERSION`.
+    pub fn migrate(self) -> Result<Self, IkError> {
+        match self.version {
+            RIG_VERSION => Ok(self),
+            found => Err(IkError::UnsupportedRigVersion {
+                found,
+                supported: RIG_VERSION,
+            }),
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn from_ron_str(source: &str) -> Result<Self, IkError> {
+        let rig: Self = ron::from_str(source).map_err(|err| IkError::Rig(err.to_string()))?;
+        rig.migrate()
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn to_ron_string(&self) -> Result<String, IkError> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|err| IkError::Rig(err.to_string()))
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn from_json_str(source: &str) -> Result<Self, IkError> {
+        let rig: Self =
+            serde_json::from_str(source).map_err(|err| IkError::Rig(err.to_string()))?;
+        rig.migrate()
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn to_json_string(&self) -> Result<String, IkError> {
+        serde_json::to_string_pretty(self).map_err(|err| IkError::Rig(err.to_string()))
+    }
+}
+
+impl FabrikChain {
+    pub fn solver_settings(&self) -> SolverSettings {
+        SolverSettings {
+            lock_ground: self.lock_ground,
+            root: self.root,
+            ground: self.ground,
+            up: self.up,
+            tolerance: self.tolerance,
+            orientation_tolerance: self.orientation_tolerance,
+            clock: self.clock,
+            recovery: self.recovery,
+            environment: self.environment.clone(),
+            solver: self.solver.kind(),
+        }
+    }
+
+    pub fn apply_solver_settings(&mut self, settings: SolverSettings) {
+        self.lock_ground = settings.lock_ground;
+        self.root = settings.root;
+        self.ground = settings.ground;
+        self.up = settings.up;
+        self.tolerance = settings.tolerance;
+        self.orientation_tolerance = settings.orientation_tolerance;
+        self.clock = settings.clock;
+        self.recovery = settings.recovery;
+        self.environment = settings.environment;
+        if let Some(solver) = settings.solver {
+            self.solver = solver.solver();
+        }
+    }
+
+    /// Builds a chain from a rig. The rig's pose becomes the one `reset` returns to.
+    pub fn from_rig(rig: Rig) -> Result<Self, IkError> {
+        let rig = rig.migrate()?;
+        let mut chain = Self::new(rig.joints, rig.motion_heuristics)?;
+        if let Some(lengths) = rig.lengths {
+            if lengths.len() != chain.lengths.len() {
+                return Err(IkError::MismatchedLengths {
+                    joints: chain.joints.len(),
+                    lengths: lengths.len(),
+                });
+            }
+            chain.lengths = lengths;
+            chain.validate()?;
+            chain.respace();
+        }
+        if !rig.constraints.is_empty() {
+            if rig.constraints.len() != chain.joints.len() {
+                return Err(IkError::MismatchedConstraints {
+                    joints: chain.joints.len(),
+                    constraints: rig.constraints.len(),
+                });
+            }
+            chain.constraints = rig.constraints;
+        }
+        chain.apply_solver_settings(rig.solver);
+        chain.validate()?;
+        chain.snapshot();
+        chain.recalculate_segments()?;
+        Ok(chain)
+    }
+
+    /// Moves the joints so every segment keeps its direction but takes on its length from
+    /// `lengths`, working out from the first joint.
+    fn respace(&mut self) {
+        let directions: Vec<Vec3> = self
+            .joints
+            .windows(2)
+            .map(|pair| (pair[1] - pair[0]).normalize())
+            .collect();
+        for (i, direction) in directions.into_iter().enumerate() {
+            self.joints[i + 1] = self.joints[i] + direction * self.lengths[i];
+        }
+    }
+
+    pub fn to_rig(&self) -> Rig {
+        Rig {
+            version: RIG_VERSION,
+            joints: self.joints.clone(),
+            lengths: Some(self.lengths.clone()),
+            constraints: self.constraints.clone(),
+            motion_heuristics: self.motion_heuristics.clone(),
+            solver: self.solver_settings(),
+        }
+    }
+}
@@ -0,0 +1,283 @@
+use crate::extern_prelude::*;
+use crate::clock::Stopwatch;
+use crate::{
+    place, targets, AnchorPoints, FabrikChain, IkError, JointConstraint, SolveReport, Target,
+};
+
+/// A tree of joints solved with multi-end-effector FABRIK.
+///
+/// Every joint but the root has a parent that comes before it in `joints`, so walking the
+/// joints in order always visits a parent before its children. A joint with several children
+/// (a torso with two arms, a palm with fingers) is a sub-base: on the way in from the tips it
+/// is placed at the centroid of where each of its branches wants it.
+///
+/// Segment `i` runs from joint `i`'s parent to joint `i`. Joints are placed exactly as
+/// `FabrikChain` places them, so targets, orientation targets, anchor points and joint
+/// constraints mean the same here, and a single-branch skeleton solves like the chain. A
+/// joint's orientation is that of the segment to its first child, or for an end effector the
+/// segment arriving at it.
+#[derive(Debug, Clone)]
+pub struct Skeleton {
+    pub joints: Vec<Vec3>,
+    pub parents: Vec<Option<usize>>,
+    /// Length of each segment, `0.0` for the root, which has none.
+    pub lengths: Vec<f32>,
+    /// The constraint at each joint, applied to the segments to its children.
+    pub constraints: Vec<JointConstraint>,
+    pub targets: Vec<Target>,
+    /// Planted joints: `(joint, position, rotation)`, as in `MotionHeuristics`.
+    pub anchor_points: AnchorPoints,
+    /// Where the root is pinned during solving, or `None` to let it move freely.
+    pub root: Option<Vec3>,
+    pub tolerance: f32,
+    pub orientation_tolerance: f32,
+    children: Vec<Vec<usize>>,
+}
+
+impl Skeleton {
+    pub fn new(joints: Vec<Vec3>, parents: Vec<Option<usize>>) -> Result<Self, IkError> {
+        if joints.is_empty() {
+            return Err(IkError::EmptyChain);
+        }
+        if parents.len() != joints.len() {
+            return Err(IkError::MismatchedLengths {
+                joints: joints.len(),
+                lengths: parents.len(),
+            });
+        }
+        let mut lengths = Vec::with_capacity(joints.len());
+        let mut children = vec![Vec::new(); joints.len()];
+        for (i, parent) in parents.iter().enumerate() {
+            match (i, *parent) {
+                (0, None) => lengths.push(0.0),
+                (_, Some(parent)) if parent < i => {
+                    let length = joints[i].distance(joints[parent]);
+                    if length <= f32::EPSILON {
+                        return Err(IkError::ZeroLengthSegment(i));
+                    }
+                    lengths.push(length);
+                    children[parent].push(i);
+                }
+                _ => return Err(IkError::InvalidParent(i)),
+            }
+        }
+        Ok(Self {
+            root: Some(joints[0]),
+            constraints: vec![JointConstraint::default(); joints.len()],
+            joints,
+            parents,
+            lengths,
+            targets: Vec::new(),
+            anchor_points: Vec::new(),
+            tolerance: 1e-3,
+            orientation_tolerance: 1e-2,
+            children,
+        })
+    }
+
+    /// The single-branch skeleton matching `chain`, with the root pinned where `lock_ground`
+    /// would pin it. The skeleton is always rooted at the chain's first joint, so a chain
+    /// with its `root` anywhere else is an `IkError::UnsupportedRoot`.
+    pub fn from_chain(chain: &FabrikChain) -> Result<Self, IkError> {
+        if chain.root != 0 {
+            return Err(IkError::UnsupportedRoot(chain.root));
+        }
+        let parents = (0..chain.joints.len()).map(|i| i.checked_sub(1)).collect();
+        let mut skeleton = Self::new(chain.joints.clone(), parents)?;
+        skeleton.lengths[1..].copy_from_slice(&chain.lengths);
+        skeleton.constraints.clone_from(&chain.constraints);
+        skeleton.targets.clone_from(&chain.targets);
+        skeleton
+            .anchor_points
+            .clone_from(&chain.motion_heuristics.anchor_points);
+        skeleton.root = chain.lock_ground.then_some(chain.ground);
+        skeleton.tolerance = chain.tolerance;
+        skeleton.orientation_tolerance = chain.orientation_tolerance;
+        Ok(skeleton)
+    }
+
+    pub fn children(&self, joint: usize) -> &[usize] {
+        &self.children[joint]
+    }
+
+    /// Joints that have no children.
+    pub fn end_effectors(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.joints.len()).filter(|joint| self.children[*joint].is_empty())
+    }
+
+    /// Indices from the root down to `tip`, inclusive.
+    pub fn branch(&self, tip: usize) -> Vec<usize> {
+        let mut branch = vec![tip];
+        let mut joint = tip;
+        while let Some(parent) = self.parents[joint] {
+            branch.push(parent);
+            joint = parent;
+        }
+        branch.reverse();
+        branch
+    }
+
+    /// The segment that gives joint `index` its orientation, `None` for a lone root.
+    pub fn orientation_segment(&self, index: usize) -> Option<usize> {
+        self.children[index]
+            .first()
+            .copied()
+            .or(self.parents[index].map(|_| index))
+    }
+
+    /// Root-to-tip direction of the segment that gives joint `index` its orientation.
+    fn orientation_direction(&self, index: usize) -> Option<Vec3> {
+        let segment = self.orientation_segment(index)?;
+        let parent = self.parents[segment]?;
+        Some(self.joints[segment] - self.joints[parent])
+    }
+
+    /// Angle between the segment of each oriented target and the direction it should face.
+    pub fn orientation_residuals(&self) -> Vec<(usize, f32)> {
+        targets::orientation_residuals(&self.targets, |index| self.orientation_direction(index))
+    }
+
+    /// Whether every target is within `tolerance` of its joint and facing the right way.
+    pub fn targets_reached(&self) -> bool {
+        targets::targets_reached(
+            &self.joints,
+            &self.targets,
+            self.tolerance,
+            self.orientation_tolerance,
+            |index| self.orientation_direction(index),
+        )
+    }
+
+    fn anchor_position(&self, index: usize) -> Option<Vec3> {
+        targets::anchor_position(&self.anchor_points, index)
+    }
+
+    fn segment_goal(&self, segment: usize) -> Option<(Vec3, f32)> {
+        targets::segment_goal(&self.targets, &self.anchor_points, segment, |index| {
+            self.orientation_segment(index)
+        })
+    }
+
+    /// Which joints have a target on themselves or somewhere below them. Only those pull on
+    /// their parents in the forward pass; the rest just follow along.
+    fn active_joints(&self) -> Vec<bool> {
+        let mut active = vec![false; self.joints.len()];
+        for target in self.targets.iter() {
+            active[target.index] = true;
+        }
+        for joint in (1..self.joints.len()).rev() {
+            if let (true, Some(parent)) = (active[joint], self.parents[joint]) {
+                active[parent] = true;
+            }
+        }
+        active
+    }
+
+    /// Where joint `parent` goes when placed along segment `child`, measured from `child`.
+    /// The constraint at `child` is measured against the segment to its first active child.
+    fn place_parent(&self, parent: usize, child: usize, active: &[bool]) -> Vec3 {
+        let from = self.joints[child];
+        let goal = self.segment_goal(child).map(|(goal, weight)| (-goal, weight));
+        let grandchildren = &self.children[child];
+        let grandchild = grandchildren
+            .iter()
+            .find(|grandchild| active[**grandchild])
+            .or(grandchildren.first());
+        let constraint = grandchild
+            .map(|grandchild| (from - self.joints[*grandchild]).normalize())
+            .map(|reference| (self.constraints[child], reference));
+        place(
+            from,
+            self.joints[parent],
+            self.lengths[child],
+            goal,
+            constraint,
+            true,
+        )
+    }
+
+    /// Where joint `joint` goes when placed along its segment, measured from its parent.
+    fn place_child(&self, joint: usize, parent: usize) -> Vec3 {
+        let from = self.joints[parent];
+        let constraint = self.parents[parent]
+            .map(|grandparent| (from - self.joints[grandparent]).normalize())
+            .map(|reference| (self.constraints[parent], reference));
+        place(
+            from,
+            self.joints[joint],
+            self.lengths[joint],
+            self.segment_goal(joint),
+            constraint,
+            false,
+        )
+    }
+
+    pub fn fwd_reach(&mut self) {
+        let active = self.active_joints();
+        for target in self.targets.iter() {
+            let joint = &mut self.joints[target.index];
+            *joint = joint.lerp(target.position, target.position_weight);
+        }
+        for (index, position, _) in self.anchor_points.iter() {
+            self.joints[*index] = *position;
+        }
+        for joint in (0..self.joints.len()).rev() {
+            let fixed = self.targets.iter().any(|target| target.index == joint)
+                || self.anchor_position(joint).is_some();
+            let placed: Vec<Vec3> = self.children[joint]
+                .iter()
+                .filter(|child| active[**child])
+                .map(|child| self.place_parent(joint, *child, &active))
+                .collect();
+            if !fixed && !placed.is_empty() {
+                self.joints[joint] = placed.iter().sum::<Vec3>() / placed.len() as f32;
+            }
+        }
+    }
+
+    pub fn bwd_reach(&mut self) {
+        if let Some(root) = self.anchor_position(0).or(self.root) {
+            self.joints[0] = root;
+        }
+        for joint in 1..self.joints.len() {
+            let Some(parent) = self.parents[joint] else {
+                continue;
+            };
+            self.joints[joint] = self
+                .anchor_position(joint)
+                .unwrap_or_else(|| self.place_child(joint, parent));
+        }
+    }
+
+    /// Runs up to `iterations` passes over the whole tree, stopping once every target is
+    /// within `tolerance`.
+    pub fn solve(&mut self, iterations: usize) -> Result<SolveReport, IkError> {
+        let anchors = self.anchor_points.iter().map(|(index, _, _)| *index);
+        for index in self.targets.iter().map(|target| target.index).chain(anchors) {
+            if index >= self.joints.len() {
+                return Err(IkError::JointOutOfRange {
+                    index,
+                    len: self.joints.len(),
+                });
+            }
+        }
+        let start = Stopwatch::start();
+        let mut iterations_used = 0;
+        for _ in 0..iterations {
+            if self.targets_reached() {
+                break;
+            }
+            iterations_used += 1;
+            self.fwd_reach();
+            self.bwd_reach();
+        }
+        Ok(SolveReport {
+            iterations: iterations_used,
+            residuals: targets::residuals(&self.joints, &self.targets),
+            orientation_residuals: self.orientation_residuals(),
+            reached: self.targets_reached(),
+            elapsed: start.elapsed(),
+            ..Default::default()
+        })
+    }
+}
@@ -0,0 +1,194 @@
+//! Chains taken from, and posed back into, glTF skins.
+//!
+//! A chain is built from the world-space positions of the nodes running from a root joint
+//! down to a tip joint, in the pose the file was loaded in. Writing a solved chain back turns
+//! each node by the same rotation its segment frame turned through since that pose, so bones
+//! keep their length and their twist follows `segment_frames`.
+
+use crate::extern_prelude::*;
+use crate::{FabrikChain, IkError, MotionHeuristics};
+use glam::Mat4;
+use gltf::json::{self, scene::UnitQuaternion};
+use std::borrow::Cow;
+
+#[derive(Debug, Clone)]
+pub struct GltfSkeleton {
+    json: json::Root,
+    /// Binary chunk of a `.glb`, written back unchanged.
+    blob: Option<Vec<u8>>,
+    parents: Vec<Option<usize>>,
+    /// World transform of every node in the pose the file was loaded in.
+    rest: Vec<Transform>,
+}
+
+impl GltfSkeleton {
+    /// Reads a `.gltf` or `.glb`. Only the node hierarchy and skins are used, so external
+    /// buffers and images are not loaded.
+    pub fn from_slice(data: &[u8]) -> Result<Self, IkError> {
+        let gltf = gltf::Gltf::from_slice(data).map_err(|err| IkError::Gltf(err.to_string()))?;
+        let json = gltf.document.into_json();
+        let mut parents = vec![None; json.nodes.len()];
+        for (parent, node) in json.nodes.iter().enumerate() {
+            for child in node.children.iter().flatten() {
+                parents[child.value()] = Some(parent);
+            }
+        }
+        let mut skeleton = Self {
+            json,
+            blob: gltf.blob,
+            parents,
+            rest: Vec::new(),
+        };
+        skeleton.rest = (0..skeleton.json.nodes.len())
+            .map(|node| skeleton.world_transform(node))
+            .collect();
+        Ok(skeleton)
+    }
+
+    /// The file with any poses written so far, as a `.glb` if it was read from one.
+    pub fn to_vec(&self) -> Result<Vec<u8>, IkError> {
+        let error = |err: &dyn std::fmt::Display| IkError::Gltf(err.to_string());
+        match &self.blob {
+            Some(blob) => {
+                let json = self.json.to_vec().map_err(|err| error(&err))?;
+                let glb = gltf::Glb {
+                    header: gltf::binary::Header {
+                        magic: *b"glTF",
+                        version: 2,
+                        // Filled in by `to_vec`.
+                        length: 0,
+                    },
+                    json: Cow::Owned(json),
+                    bin: Some(Cow::Borrowed(blob)),
+                };
+                glb.to_vec().map_err(|err| error(&err))
+            }
+            None => self.json.to_vec_pretty().map_err(|err| error(&err)),
+        }
+    }
+
+    /// Index of the node called `name`.
+    pub fn node(&self, name: &str) -> Option<usize> {
+        self.json
+            .nodes
+            .iter()
+            .position(|node| node.name.as_deref() == Some(name))
+    }
+
+    /// Nodes from joint `root` down to joint `tip`, both of which have to be joints of a skin.
+    pub fn path(&self, root: &str, tip: &str) -> Result<Vec<usize>, IkError> {
+        let joint = |name: &str| {
+            self.node(name)
+                .filter(|node| {
+                    self.json
+                        .skins
+                        .iter()
+                        .any(|skin| skin.joints.iter().any(|joint| joint.value() == *node))
+                })
+                .ok_or_else(|| IkError::Gltf(format!("{name} is not a skin joint")))
+        };
+        let (root_node, tip_node) = (joint(root)?, joint(tip)?);
+        let mut path = vec![tip_node];
+        while path[path.len() - 1] != root_node {
+            match self.parents[path[path.len() - 1]] {
+                Some(parent) if path.len() <= self.parents.len() => path.push(parent),
+                _ => return Err(IkError::Gltf(format!("{tip} is not below {root}"))),
+            }
+        }
+        path.reverse();
+        Ok(path)
+    }
+
+    /// Current transform of `node` relative to its parent.
+    pub fn local_transform(&self, node: usize) -> Transform {
+        let node = &self.json.nodes[node];
+        if let Some(matrix) = node.matrix {
+            return Transform::from_matrix(Mat4::from_cols_array(&matrix));
+        }
+        Transform {
+            translation: node.translation.map(Vec3::from).unwrap_or(Vec3::ZERO),
+            rotation: node
+                .rotation
+                .map(|UnitQuaternion(rotation)| Quat::from_array(rotation))
+                .unwrap_or(Quat::IDENTITY),
+            scale: node.scale.map(Vec3::from).unwrap_or(Vec3::ONE),
+        }
+    }
+
+    /// Current world transform of `node`.
+    pub fn world_transform(&self, node: usize) -> Transform {
+        let local = self.local_transform(node);
+        match self.parents[node] {
+            Some(parent) => self.world_transform(parent).mul_transform(local),
+            None => local,
+        }
+    }
+
+    fn set_local_transform(&mut self, node: usize, transform: Transform) {
+        let node = &mut self.json.nodes[node];
+        node.matrix = None;
+        node.translation = Some(transform.translation.to_array());
+        node.rotation = Some(UnitQuaternion(transform.rotation.normalize().to_array()));
+        node.scale = Some(transform.scale.to_array());
+    }
+
+    /// Poses the nodes from `root` to `tip` like `chain`, which has to have been built from
+    /// the same joints with `FabrikChain::from_gltf`.
+    pub fn write_pose(
+        &mut self,
+        root: &str,
+        tip: &str,
+        chain: &FabrikChain,
+    ) -> Result<(), IkError> {
+        let path = self.path(root, tip)?;
+        if path.len() != chain.joints.len() {
+            return Err(IkError::Gltf(format!(
+                "chain has {} joints but {root} to {tip} has {}",
+                chain.joints.len(),
+                path.len()
+            )));
+        }
+        let rest: Vec<Vec3> = path
+            .iter()
+            .map(|node| self.rest[*node].translation)
+            .collect();
+        let turns = chain.turns_since(&rest)?;
+
+        let mut parent_world = match self.parents[path[0]] {
+            Some(parent) => self.world_transform(parent),
+            None => Transform::IDENTITY,
+        };
+        for (k, node) in path.iter().enumerate() {
+            let mut world = self.rest[*node];
+            world.rotation = (turns[k] * world.rotation).normalize();
+            let mut local = self.local_transform(*node);
+            if k == 0 {
+                world.translation = chain.joints[0];
+                local.translation = parent_world
+                    .compute_matrix()
+                    .inverse()
+                    .transform_point3(world.translation);
+            }
+            local.rotation = parent_world.rotation.inverse() * world.rotation;
+            self.set_local_transform(*node, local);
+            parent_world = self.world_transform(*node);
+        }
+        Ok(())
+    }
+}
+
+impl FabrikChain {
+    /// Builds the chain running from skin joint `root` to skin joint `tip`, with the rest pose
+    /// and bone lengths the skeleton was loaded with. The chain is grounded where `root` is.
+    pub fn from_gltf(skeleton: &GltfSkeleton, root: &str, tip: &str) -> Result<Self, IkError> {
+        let joints = skeleton
+            .path(root, tip)?
+            .into_iter()
+            .map(|node| skeleton.rest[node].translation)
+            .collect();
+        let mut chain = Self::new(joints, MotionHeuristics::default())?;
+        chain.ground = chain.joints[0];
+        chain.snapshot();
+        Ok(chain)
+    }
+}
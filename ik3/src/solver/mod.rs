@@ -0,0 +1,167 @@
+use crate::extern_prelude::*;
+use crate::{DampedLeastSquares, FabrikChain};
+use core::{fmt, ops::Range};
+
+/// An iterative IK method. `FabrikChain::solve` calls `step` once per pass until the
+/// targets are reached or the passes run out, so every solver shares the chain's targets,
+/// constraints, root and reporting.
+pub trait Solver: fmt::Debug + Send + Sync {
+    /// Moves the chain one pass closer to its targets, keeping segment lengths and the root
+    /// where `lock_ground` wants it.
+    fn step(&self, chain: &mut FabrikChain);
+
+    /// Which of the built-in solvers this is, so rigs can save it. Solvers defined outside
+    /// this crate return `None` and are not saved.
+    fn kind(&self) -> Option<SolverKind> {
+        None
+    }
+}
+
+/// The built-in solvers, as stored in a rig.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SolverKind {
+    #[default]
+    Fabrik,
+    Ccd,
+    DampedLeastSquares(DampedLeastSquares),
+}
+
+impl SolverKind {
+    pub fn solver(self) -> Arc<dyn Solver> {
+        match self {
+            SolverKind::Fabrik => Arc::new(Fabrik),
+            SolverKind::Ccd => Arc::new(Ccd),
+            SolverKind::DampedLeastSquares(dls) => Arc::new(dls),
+        }
+    }
+}
+
+/// Forward And Backward Reaching Inverse Kinematics, the default: pulls the target joints
+/// onto their targets, then lays the chain back out from them towards the root and from the
+/// root outwards again. Honours anchor points and orientation targets.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Fabrik;
+
+impl Solver for Fabrik {
+    fn step(&self, chain: &mut FabrikChain) {
+        for target in chain.targets.iter() {
+            let joint = &mut chain.joints[target.index];
+            *joint = joint.lerp(target.position, target.position_weight);
+        }
+        for (index, position, _) in chain.motion_heuristics.anchor_points.iter() {
+            chain.joints[*index] = *position;
+        }
+        chain.fwd_reach();
+        if let Some(root) = chain.pinned_root() {
+            chain.joints[chain.root] = root;
+        }
+        chain.bwd_reach();
+    }
+
+    fn kind(&self) -> Option<SolverKind> {
+        Some(SolverKind::Fabrik)
+    }
+}
+
+/// Cyclic Coordinate Descent: for every target, turns each joint between the root and the
+/// target joint in turn, starting next to the target, so that the target joint swings
+/// towards its target. Tends to bend the joints near the tip first, where FABRIK spreads the
+/// bend along the chain. Only target positions are followed, and of the anchor points only
+/// the one at the root is held.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Ccd;
+
+impl Solver for Ccd {
+    fn step(&self, chain: &mut FabrikChain) {
+        if let Some(root) = chain.pinned_root() {
+            let offset = root - chain.joints[chain.root];
+            for joint in chain.joints.iter_mut() {
+                *joint += offset;
+            }
+        }
+        for t in 0..chain.targets.len() {
+            let target = chain.targets[t];
+            let goal = chain.joints[target.index].lerp(target.position, target.position_weight);
+            if target.index > chain.root {
+                for pivot in (chain.root..target.index).rev() {
+                    turn(chain, pivot, target.index, goal, true);
+                }
+            } else {
+                for pivot in target.index + 1..=chain.root {
+                    turn(chain, pivot, target.index, goal, false);
+                }
+            }
+        }
+    }
+
+    fn kind(&self) -> Option<SolverKind> {
+        Some(SolverKind::Ccd)
+    }
+}
+
+/// Turns the part of the chain beyond `pivot` (towards the tip if `upward`, towards the
+/// start otherwise) about it so `effector` points at `goal`, then pulls the segment leaving
+/// `pivot` back inside its constraint.
+fn turn(chain: &mut FabrikChain, pivot: usize, effector: usize, goal: Vec3, upward: bool) {
+    let center = chain.joints[pivot];
+    let (Some(from), Some(to)) = (
+        (chain.joints[effector] - center).try_normalize(),
+        (goal - center).try_normalize(),
+    ) else {
+        return;
+    };
+    rotate(chain, beyond(chain, pivot, upward), center, arc(from, to));
+    constrain(chain, pivot, upward);
+}
+
+/// The joints moved by turning about `pivot`: those towards the tip if `upward`, towards the
+/// start otherwise.
+pub(crate) fn beyond(chain: &FabrikChain, pivot: usize, upward: bool) -> Range<usize> {
+    if upward {
+        pivot + 1..chain.joints.len()
+    } else {
+        0..pivot
+    }
+}
+
+/// Turns the joints beyond `pivot` so the segment leaving it is back inside the constraint
+/// at `pivot`, which is measured against the segment on the root side of it.
+pub(crate) fn constrain(chain: &mut FabrikChain, pivot: usize, upward: bool) {
+    let (neighbour, parent) = if upward {
+        (pivot + 1, pivot.checked_sub(1))
+    } else {
+        (pivot - 1, Some(pivot + 1))
+    };
+    let (Some(constraint), Some(parent)) = (
+        chain.constraints.get(pivot).copied(),
+        parent.and_then(|parent| chain.joints.get(parent).copied()),
+    ) else {
+        return;
+    };
+    let center = chain.joints[pivot];
+    let reference = (center - parent).normalize();
+    let direction = (chain.joints[neighbour] - center).normalize();
+    let constrained = constraint.apply(reference, direction, !upward).normalize();
+    let moving = beyond(chain, pivot, upward);
+    rotate(chain, moving, center, arc(direction, constrained));
+}
+
+/// Rotation from unit vector `from` onto unit vector `to`. Unlike `Quat::from_rotation_arc`,
+/// which snaps turns of under about a milliradian to nothing, this keeps the small turns CCD
+/// converges with.
+fn arc(from: Vec3, to: Vec3) -> Quat {
+    let axis = from.cross(to);
+    match axis.try_normalize() {
+        Some(unit) => Quat::from_axis_angle(unit, axis.length().atan2(from.dot(to))),
+        // Parallel, or exactly opposite.
+        None if from.dot(to) > 0.0 => Quat::IDENTITY,
+        None => Quat::from_axis_angle(from.any_orthonormal_vector(), PI),
+    }
+}
+
+pub(crate) fn rotate(chain: &mut FabrikChain, joints: Range<usize>, center: Vec3, rotation: Quat) {
+    for joint in &mut chain.joints[joints] {
+        *joint = center + rotation * (*joint - center);
+    }
+}
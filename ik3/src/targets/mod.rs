@@ -0,0 +1,167 @@
+use crate::extern_prelude::*;
+use crate::{AnchorPoints, FabrikChain};
+
+/// Axis of a segment in its own frame. Matches the Y-up cylinders `segment_transforms` places.
+pub const BONE_AXIS: Vec3 = Vec3::Y;
+
+/// Where a joint should end up, and optionally how it should be oriented.
+///
+/// A joint's orientation is that of the segment leaving it towards the tip, or for the last
+/// joint the segment arriving at it, with `BONE_AXIS` pointing from root to tip. Only the
+/// direction of the segment can be steered this way; twist about the segment is left free.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Target {
+    pub index: usize,
+    pub position: Vec3,
+    pub orientation: Option<Quat>,
+    /// How far towards `position` the joint is pulled on each pass, from `0.0` to `1.0`.
+    pub position_weight: f32,
+    /// How far towards `orientation` the segment is turned on each pass, from `0.0` to `1.0`.
+    pub orientation_weight: f32,
+}
+
+impl Target {
+    pub fn new(index: usize, position: Vec3) -> Self {
+        Self {
+            index,
+            position,
+            orientation: None,
+            position_weight: 1.0,
+            orientation_weight: 1.0,
+        }
+    }
+
+    pub fn with_orientation(mut self, orientation: Quat, weight: f32) -> Self {
+        self.orientation = Some(orientation);
+        self.orientation_weight = weight;
+        self
+    }
+
+    pub fn with_position_weight(mut self, weight: f32) -> Self {
+        self.position_weight = weight;
+        self
+    }
+}
+
+impl From<(usize, Vec3)> for Target {
+    fn from((index, position): (usize, Vec3)) -> Self {
+        Self::new(index, position)
+    }
+}
+
+impl FabrikChain {
+    /// Index of the segment whose direction gives joint `index` its orientation, `None` for a
+    /// single-joint chain, which has no segments.
+    pub fn orientation_segment(&self, index: usize) -> Option<usize> {
+        let last = self.lengths.len().checked_sub(1)?;
+        Some(index.min(last))
+    }
+
+    /// Current orientation of joint `index`, taken from the shortest arc between `BONE_AXIS`
+    /// and its segment. `None` for a single-joint chain.
+    pub fn joint_orientation(&self, index: usize) -> Option<Quat> {
+        let segment = self.orientation_segment(index)?;
+        let direction = (self.joints[segment + 1] - self.joints[segment]).normalize();
+        Some(Quat::from_rotation_arc(BONE_AXIS, direction))
+    }
+
+    /// Angle between the segment of each oriented target and the direction it should face.
+    pub fn orientation_residuals(&self) -> Vec<(usize, f32)> {
+        orientation_residuals(&self.targets, |index| {
+            self.joint_orientation(index).map(|orientation| orientation * BONE_AXIS)
+        })
+    }
+
+    /// Where joint `index` is pinned by an anchor point, if anywhere.
+    pub(crate) fn anchor_position(&self, index: usize) -> Option<Vec3> {
+        anchor_position(&self.motion_heuristics.anchor_points, index)
+    }
+
+    /// The root-to-tip direction segment `segment` is being turned towards, and how strongly.
+    pub(crate) fn segment_goal(&self, segment: usize) -> Option<(Vec3, f32)> {
+        segment_goal(
+            &self.targets,
+            &self.motion_heuristics.anchor_points,
+            segment,
+            |index| self.orientation_segment(index),
+        )
+    }
+}
+
+/// Distance between each target's joint in `joints` and its target position.
+pub(crate) fn residuals(joints: &[Vec3], targets: &[Target]) -> Vec<(usize, f32)> {
+    targets
+        .iter()
+        .map(|target| (target.index, joints[target.index].distance(target.position)))
+        .collect()
+}
+
+/// Angle between the segment of each oriented target and the direction it should face.
+/// `direction` gives the root-to-tip direction of the segment orienting a joint, if it has one.
+pub(crate) fn orientation_residuals(
+    targets: &[Target],
+    direction: impl Fn(usize) -> Option<Vec3>,
+) -> Vec<(usize, f32)> {
+    targets
+        .iter()
+        .filter_map(|target| {
+            let orientation = target.orientation?;
+            let actual = direction(target.index)?;
+            Some((target.index, actual.angle_between(orientation * BONE_AXIS)))
+        })
+        .collect()
+}
+
+/// Whether every target is within `tolerance` of its joint in `joints`, and facing within
+/// `orientation_tolerance` of the right way, with `direction` as in `orientation_residuals`.
+pub(crate) fn targets_reached(
+    joints: &[Vec3],
+    targets: &[Target],
+    tolerance: f32,
+    orientation_tolerance: f32,
+    direction: impl Fn(usize) -> Option<Vec3>,
+) -> bool {
+    targets
+        .iter()
+        .all(|target| joints[target.index].distance(target.position) <= tolerance)
+        && orientation_residuals(targets, direction)
+            .iter()
+            .all(|(_, residual)| *residual <= orientation_tolerance)
+}
+
+/// Where joint `index` is pinned by one of `anchor_points`, if anywhere.
+pub(crate) fn anchor_position(anchor_points: &AnchorPoints, index: usize) -> Option<Vec3> {
+    anchor_points
+        .iter()
+        .find(|(anchor, _, _)| *anchor == index)
+        .map(|(_, position, _)| *position)
+}
+
+/// The root-to-tip direction segment `segment` is being turned towards, and how strongly,
+/// from `targets` and `anchor_points`. Anchors always win over targets. `orientation_segment`
+/// gives the segment orienting a joint, if it has one.
+pub(crate) fn segment_goal(
+    targets: &[Target],
+    anchor_points: &AnchorPoints,
+    segment: usize,
+    orientation_segment: impl Fn(usize) -> Option<usize>,
+) -> Option<(Vec3, f32)> {
+    let anchor = anchor_points
+        .iter()
+        .find(|(index, _, _)| orientation_segment(*index) == Some(segment))
+        .map(|(_, _, rotation)| (*rotation * BONE_AXIS, 1.0));
+    anchor.or_else(|| {
+        targets.iter().find_map(|target| {
+            let orientation = target.orientation?;
+            (orientation_segment(target.index) == Some(segment))
+                .then(|| (orientation * BONE_AXIS, target.orientation_weight))
+        })
+    })
+}
+
+/// Turns `direction` towards `goal` by `weight` of the arc between them.
+pub(crate) fn steer(direction: Vec3, goal: Vec3, weight: f32) -> Vec3 {
+    let arc = Quat::from_rotation_arc(direction, goal.normalize());
+    Quat::IDENTITY.slerp(arc, weight.clamp(0.0, 1.0)) * direction
+}
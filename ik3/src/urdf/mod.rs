@@ -0,0 +1,241 @@
+//! Building chains from URDF robot descriptions.
+//!
+//! Only the kinematic part of a URDF is read: every `<joint>` with its type, parent and child
+//! link, `<origin>`, `<axis>` and `<limit>`. Links are only used to walk from one joint to the
+//! next, so their geometry and inertia are ignored.
+
+use crate::extern_prelude::*;
+use crate::{FabrikChain, IkError, JointConstraint, MotionHeuristics};
+use glam::EulerRot;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UrdfJointKind {
+    Revolute,
+    Continuous,
+    Prismatic,
+    Fixed,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UrdfJoint {
+    pub name: String,
+    pub kind: UrdfJointKind,
+    pub parent: String,
+    pub child: String,
+    /// Pose of the joint frame in the parent link's frame.
+    pub origin: Transform,
+    /// Axis of rotation or translation, in the joint frame.
+    pub axis: Vec3,
+    /// `(lower, upper)` in radians, or metres for prismatic joints.
+    pub limit: Option<(f32, f32)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Urdf {
+    pub name: String,
+    pub joints: Vec<UrdfJoint>,
+}
+
+impl Urdf {
+    pub fn parse(source: &str) -> Result<Self, IkError> {
+        let document =
+            roxmltree::Document::parse(source).map_err(|err| IkError::Urdf(err.to_string()))?;
+        let robot = document.root_element();
+        if !robot.has_tag_name("robot") {
+            return Err(IkError::Urdf(format!(
+                "expected a <robot> element, found <{}>",
+                robot.tag_name().name()
+            )));
+        }
+        let joints = robot
+            .children()
+            .filter(|node| node.has_tag_name("joint"))
+            .map(parse_joint)
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            name: robot.attribute("name").unwrap_or_default().to_owned(),
+            joints,
+        })
+    }
+
+    /// The joints leading from `base` link to `tip` link, in order.
+    pub fn path(&self, base: &str, tip: &str) -> Result<Vec<&UrdfJoint>, IkError> {
+        let mut path = Vec::new();
+        let mut link = tip;
+        while link != base {
+            let joint = self
+                .joints
+                .iter()
+                .find(|joint| joint.child == link)
+                .ok_or_else(|| IkError::Urdf(format!("link {link} is not below link {base}")))?;
+            if path.len() == self.joints.len() {
+                return Err(IkError::Urdf(format!("link {link} is part of a cycle")));
+            }
+            path.push(joint);
+            link = &joint.parent;
+        }
+        path.reverse();
+        Ok(path)
+    }
+}
+
+fn parse_joint(node: roxmltree::Node) -> Result<UrdfJoint, IkError> {
+    let name = node.attribute("name").unwrap_or_default().to_owned();
+    let missing = |what: &str| IkError::Urdf(format!("joint {name} has no {what}"));
+    let kind = match node.attribute("type") {
+        Some("revolute") => UrdfJointKind::Revolute,
+        Some("continuous") => UrdfJointKind::Continuous,
+        Some("prismatic") => UrdfJointKind::Prismatic,
+        Some("fixed") => UrdfJointKind::Fixed,
+        Some(other) => {
+            return Err(IkError::Urdf(format!(
+                "joint {name} has unsupported type {other}"
+            )))
+        }
+        None => return Err(missing("type")),
+    };
+    let child_attribute = |tag: &str, attribute: &str| {
+        node.children()
+            .find(|child| child.has_tag_name(tag))
+            .and_then(|child| child.attribute(attribute))
+    };
+    let parent = child_attribute("parent", "link").ok_or_else(|| missing("parent"))?;
+    let child = child_attribute("child", "link").ok_or_else(|| missing("child"))?;
+    let xyz = parse_vec3(&name, child_attribute("origin", "xyz"))?.unwrap_or(Vec3::ZERO);
+    let rpy = parse_vec3(&name, child_attribute("origin", "rpy"))?.unwrap_or(Vec3::ZERO);
+    let axis = parse_vec3(&name, child_attribute("axis", "xyz"))?.unwrap_or(Vec3::X);
+    let axis = axis
+        .try_normalize()
+        .ok_or_else(|| IkError::Urdf(format!("joint {name} has a zero axis")))?;
+    let limit = match kind {
+        UrdfJointKind::Revolute | UrdfJointKind::Prismatic => {
+            let bound = |attribute| {
+                parse_f32(&name, child_attribute("limit", attribute)).map(Option::unwrap_or_default)
+            };
+            Some((bound("lower")?, bound("upper")?))
+        }
+        UrdfJointKind::Continuous | UrdfJointKind::Fixed => None,
+    };
+    Ok(UrdfJoint {
+        kind,
+        parent: parent.to_owned(),
+        child: child.to_owned(),
+        // URDF's rpy are fixed-axis rotations about X, then Y, then Z.
+        origin: Transform::from_translation(xyz)
+            .with_rotation(Quat::from_euler(EulerRot::ZYX, rpy.z, rpy.y, rpy.x)),
+        axis,
+        limit,
+        name,
+    })
+}
+
+fn parse_f32(joint: &str, value: Option<&str>) -> Result<Option<f32>, IkError> {
+    value
+        .map(|value| {
+            value.trim().parse().map_err(|_| {
+                IkError::Urdf(format!("joint {joint} has an invalid number {value:?}"))
+            })
+        })
+        .transpose()
+}
+
+fn parse_vec3(joint: &str, value: Option<&str>) -> Result<Option<Vec3>, IkError> {
+    let Some(value) = value else {
+        return Ok(None);
+    };
+    let components = value
+        .split_whitespace()
+        .map(|component| parse_f32(joint, Some(component)).map(Option::unwrap_or_default))
+        .collect::<Result<Vec<f32>, _>>()?;
+    match components[..] {
+        [x, y, z] => Ok(Some(Vec3::new(x, y, z))),
+        _ => Err(IkError::Urdf(format!(
+            "joint {joint} has {value:?} where three numbers were expected"
+        ))),
+    }
+}
+
+/// Signed bend from `reference` to `direction` about `axis`, as `JointConstraint::Hinge`
+/// measures it.
+fn hinge_bend(axis: Vec3, reference: Vec3, direction: Vec3) -> f32 {
+    let ref_proj = reference - axis * reference.dot(axis);
+    let dir_proj = direction - axis * direction.dot(axis);
+    axis.dot(ref_proj.cross(dir_proj))
+        .atan2(ref_proj.dot(dir_proj))
+}
+
+/// A hinge that holds `direction` where it is relative to `reference`.
+fn locked(reference: Vec3, direction: Vec3) -> JointConstraint {
+    let axis = reference
+        .cross(direction)
+        .try_normalize()
+        .unwrap_or_else(|| reference.any_orthonormal_vector());
+    let bend = hinge_bend(axis, reference, direction);
+    JointConstraint::Hinge {
+        axis,
+        min: bend,
+        max: bend,
+    }
+}
+
+impl FabrikChain {
+    /// Builds the chain running from link `base` to link `tip` of a URDF, in `base`'s frame
+    /// with Z up. Every joint along the way becomes a joint of the chain, except that joints
+    /// sitting at the same point are merged, since the chain cannot have zero-length segments.
+    ///
+    /// Revolute joints become hinges about their axis, with their limits measured from the
+    /// rest pose, and continuous joints become hinges without limits. The chain has no way to
+    /// change its segment lengths, so prismatic joints are locked in place like fixed ones, and
+    /// neither can a revolute joint turn the segment lying along its own axis, so those are
+    /// locked as well. Where several moving joints are merged into one, it is left
+    /// unconstrained.
+    pub fn from_urdf(urdf: &Urdf, base: &str, tip: &str) -> Result<Self, IkError> {
+        let path = urdf.path(base, tip)?;
+        // World frame of every joint, and the joints merged at each point of the chain.
+        let mut positions = vec![Vec3::ZERO];
+        let mut merged: Vec<Vec<(&UrdfJoint, Quat)>> = vec![Vec::new()];
+        let mut frame = Transform::IDENTITY;
+        for joint in path {
+            frame = frame.mul_transform(joint.origin);
+            let last = positions.len() - 1;
+            if frame.translation.distance(positions[last]) > f32::EPSILON {
+                positions.push(frame.translation);
+                merged.push(Vec::new());
+            }
+            merged.last_mut().unwrap().push((joint, frame.rotation));
+        }
+
+        let mut chain = Self::new(positions, MotionHeuristics::default())?;
+        chain.up = Vec3::Z;
+        let interior = merged.iter().enumerate().take(chain.lengths.len()).skip(1);
+        for (i, joints) in interior {
+            let reference = (chain.joints[i] - chain.joints[i - 1]).normalize();
+            let direction = (chain.joints[i + 1] - chain.joints[i]).normalize();
+            let moving: Vec<_> = joints
+                .iter()
+                .filter(|(joint, _)| {
+                    matches!(
+                        joint.kind,
+                        UrdfJointKind::Revolute | UrdfJointKind::Continuous
+                    )
+                })
+                .collect();
+            let constraint = match moving[..] {
+                [(joint, rotation)] if (*rotation * joint.axis).cross(direction).length() > 1e-4 => {
+                    let axis = *rotation * joint.axis;
+                    let bend = hinge_bend(axis, reference, direction);
+                    let (lower, upper) = joint.limit.unwrap_or((-PI, PI));
+                    JointConstraint::Hinge {
+                        axis,
+                        min: bend + lower,
+                        max: bend + upper,
+                    }
+                }
+                [] | [_] => locked(reference, direction),
+                _ => JointConstraint::Unconstrained,
+            };
+            chain.set_constraint(i, constraint)?;
+        }
+        Ok(chain)
+    }
+}
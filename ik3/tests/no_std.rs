@@ -0,0 +1,71 @@
+//! Uses the core from a `no_std` crate. Built with `--no-default-features --features libm`,
+//! this also checks that ik3 itself compiles without `std`.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{sync::Arc, vec};
+use core::f32::consts::PI;
+use glam::Vec3;
+use ik3::{
+    Ccd, ChainBatch, Clock, DampedLeastSquares, FabrikChain, JointConstraint, KinematicsMode,
+    MotionHeuristics, PoseDiscrepancy, Solver, Target, BONE_AXIS,
+};
+
+fn leg() -> FabrikChain {
+    let joints = vec![Vec3::ZERO, Vec3::Y, Vec3::Y * 2.0, Vec3::Y * 3.0];
+    let mut chain = FabrikChain::new(joints, MotionHeuristics::default()).unwrap();
+    let knee = JointConstraint::Hinge {
+        axis: Vec3::Z,
+        min: -PI / 2.0,
+        max: 0.0,
+    };
+    chain.set_constraint(1, knee).unwrap();
+    chain.set_constraint(2, knee).unwrap();
+    chain.clock = Clock::FixedStep(core::time::Duration::from_millis(2));
+    chain.targets.push(Target::new(3, Vec3::new(1.5, 1.5, 0.0)));
+    chain
+}
+
+#[test]
+fn solves_without_std() {
+    let solvers: [Arc<dyn Solver>; 3] = [
+        Arc::new(ik3::Fabrik),
+        Arc::new(Ccd),
+        Arc::new(DampedLeastSquares::default()),
+    ];
+    for solver in solvers {
+        let mut chain = leg();
+        chain.solver = solver;
+        let report = chain
+            .solve(100, PoseDiscrepancy::default(), &mut KinematicsMode::default())
+            .unwrap();
+        assert!(report.reached, "{report:?}");
+        assert_eq!(chain.joints[0], Vec3::ZERO);
+        for i in 0..chain.lengths.len() {
+            let length = chain.joints[i].distance(chain.joints[i + 1]);
+            assert!((length - chain.lengths[i]).abs() < 1e-4);
+        }
+        assert_eq!(chain.angles.len(), chain.joints.len());
+        for (i, frame) in chain.segment_frames().into_iter().enumerate() {
+            let direction = (chain.joints[i + 1] - chain.joints[i]).normalize();
+            assert!((frame * BONE_AXIS).distance(direction) < 1e-4);
+        }
+    }
+}
+
+#[test]
+fn batch_without_std() {
+    let mut batch = ChainBatch::new();
+    batch.push(&leg()).unwrap();
+    let mut swung = leg();
+    swung.targets[0].position = Vec3::new(-1.5, 1.5, 0.0);
+    swung.set_constraint(1, JointConstraint::Unconstrained).unwrap();
+    swung.set_constraint(2, JointConstraint::Unconstrained).unwrap();
+    batch.push(&swung).unwrap();
+    for report in batch.solve(100) {
+        assert!(report.unwrap().reached);
+    }
+    assert!(batch.joints(1).unwrap()[3].distance(Vec3::new(-1.5, 1.5, 0.0)) < 1e-2);
+}
@@ -200,7 +200,8 @@ fn setup(
         Vec3::new(3.0, 0.0, 0.0),
         Vec3::new(4.0, 0.0, 0.0),
     ];
-    let mut limb = FabrikChain::new(joints, MotionHeuristics::default());
+    let mut limb = FabrikChain::new(joints, MotionHeuristics::default())
+        .expect("Demo limb should be a valid chain");
     commands.spawn(VelocityDisplay::default());
 
     // Some light to see something
@@ -378,7 +379,7 @@ fn move_limb(
             .expect("Something is moving but it's not a ball!");
         excluded.push(ball.index);
         limb.targets
-            .push((ball.index, transform.translation.clone()));
+            .push((ball.index, transform.translation.clone()).into());
     }
     ev_recompute.send_default();
 }
@@ -387,12 +388,15 @@ fn recompute_limb(
     mut query_chain: Query<&mut LimbData>,
     mut query_velocity_display: Query<&mut VelocityDisplay>,
     mut ev_sync_transforms: EventWriter<SyncTransforms>,
+    mut ui_state: ResMut<UiState>,
     limb_state: Res<State<LimbState>>,
 ) {
     let mut chain = query_chain.single_mut();
     let limb = chain.get_mut(limb_state.get());
 
-    limb.solve(10, PoseDiscrepancy::default());
+    if let Err(err) = limb.solve(10, PoseDiscrepancy::default(), &mut ui_state.kinematics_mode) {
+        warn!("Could not solve limb: {err}");
+    }
 
     if !limb.angular_velocities.is_empty() {
         query_velocity_display
@@ -420,7 +424,9 @@ fn display_ui(
         }
         if ui.button("Reset all").clicked() {
             velocity_display.0.clear();
-            chain.0.reset();
+            if let Err(err) = chain.0.reset() {
+                warn!("Could not reset limb: {err}");
+            }
             ev_sync_transforms.send_default();
         }
         if ui